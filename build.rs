@@ -1,3 +1,49 @@
+/// Trace levels in ascending order, as used by the `max-level-*` features
+const LEVELS: [&str; 6] = ["trace", "debug", "info", "warning", "error", "fatal"];
+
+/// Returns the index of the lowest level allowed by the features with the given prefix. If
+/// multiple features are enabled, the most restrictive one wins
+fn min_level(prefix: &str, default: usize) -> usize {
+    let feature = |level: &str| {
+        let name = format!("CARGO_FEATURE_{}_{}", prefix, level.to_uppercase());
+        std::env::var_os(name).is_some()
+    };
+
+    if feature("off") {
+        return LEVELS.len();
+    }
+    LEVELS
+        .iter()
+        .rposition(|level| feature(level))
+        .unwrap_or(default)
+}
+
+/// Sizes of the format buffer selectable with the `format-buffer-*` features
+const BUFFER_SIZES: [usize; 5] = [128, 256, 512, 2048, 4096];
+const DEFAULT_BUFFER_SIZE: usize = 1024;
+/// Smallest format buffer, the size of the smallest `format-buffer-*` feature. It fits a full
+/// hex dump row with the level prefix, and the header of an interned frame with its text
+const MIN_BUFFER_SIZE: usize = 128;
+
+/// Returns the size of the format buffer. The `TRACE_FORMAT_BUFFER_SIZE` environment variable
+/// takes precedence over the features, and the smallest size wins if several features are enabled
+fn buffer_size() -> usize {
+    println!("cargo:rerun-if-env-changed=TRACE_FORMAT_BUFFER_SIZE");
+    if let Ok(size) = std::env::var("TRACE_FORMAT_BUFFER_SIZE") {
+        return match size.parse() {
+            Ok(size) if size >= MIN_BUFFER_SIZE => size,
+            _ => panic!(
+                "TRACE_FORMAT_BUFFER_SIZE must be a number of at least {MIN_BUFFER_SIZE}, not {size:?}"
+            ),
+        };
+    }
+
+    BUFFER_SIZES
+        .into_iter()
+        .find(|size| std::env::var_os(format!("CARGO_FEATURE_FORMAT_BUFFER_{size}")).is_some())
+        .unwrap_or(DEFAULT_BUFFER_SIZE)
+}
+
 fn main() {
     println!("cargo:rerun-if-changed=src/weak_on_trace.c");
 
@@ -9,4 +55,36 @@ fn main() {
             .file("src/weak_on_trace.c")
             .compile("weak_on_trace");
     }
+
+    // Decide which levels the trace macros are generated for. `trace_level_*` levels are traced
+    // when the trace crate is built with debug assertions, and `trace_release_level_*` levels are
+    // traced without them as well. `always-on` traces the same levels in both, and `always-off`
+    // traces nothing, taking precedence over `always-on`
+    let feature = |name: &str| std::env::var_os(format!("CARGO_FEATURE_{name}")).is_some();
+    let max_level = match feature("ALWAYS_OFF") {
+        true => LEVELS.len(),
+        false => min_level("MAX_LEVEL", 0),
+    };
+    let release_max_level = match feature("ALWAYS_ON") {
+        true => max_level,
+        false => usize::max(max_level, min_level("RELEASE_MAX_LEVEL", LEVELS.len())),
+    };
+    for (i, level) in LEVELS.iter().enumerate() {
+        println!("cargo:rustc-check-cfg=cfg(trace_level_{level})");
+        println!("cargo:rustc-check-cfg=cfg(trace_release_level_{level})");
+        if i >= max_level {
+            println!("cargo:rustc-cfg=trace_level_{level}");
+        }
+        if i >= release_max_level {
+            println!("cargo:rustc-cfg=trace_release_level_{level}");
+        }
+    }
+
+    // The format buffer size is included by lib.rs as a constant
+    let config = format!(
+        "pub(crate) const TRACE_FORMAT_BUFFER_SIZE: usize = {};\n",
+        buffer_size()
+    );
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(std::path::Path::new(&out_dir).join("config.rs"), config).unwrap();
 }
@@ -0,0 +1,82 @@
+//! Failure handling of the assertion macros, such as [`trace_assert!`](crate::trace_assert). The
+//! failed assertion is traced before anything else happens, so that it reaches the trace handler
+//! even when the panic message doesn't
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering::Relaxed},
+};
+
+use crate::{Callsite, Level, Styled, Timestamp, flush, handler_enabled, trace_format_at};
+
+/// What to do after a failed assertion has been traced
+#[repr(u8)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AssertAction {
+    /// Panic with the assertion message, as `assert!` does. This is the default. With the
+    /// `panic-handler` feature the message is already traced, so the panic only refers to it
+    Panic,
+    /// Flush the trace handler and loop forever, without going through the panic handler
+    Halt,
+    /// Carry on after the assertion
+    Continue,
+}
+
+static ACTION: AtomicU8 = AtomicU8::new(AssertAction::Panic as u8);
+
+pub fn set_assert_action(action: AssertAction) {
+    ACTION.store(action as u8, Relaxed);
+}
+
+pub fn assert_action() -> AssertAction {
+    match ACTION.load(Relaxed) {
+        1 => AssertAction::Halt,
+        2 => AssertAction::Continue,
+        _ => AssertAction::Panic,
+    }
+}
+
+/// Trace a failed assertion at the error level, with the location of the callsite, and then act on
+/// the [`AssertAction`]. Used by the assertion macros
+pub fn assert_failed(callsite: &Callsite, args: fmt::Arguments) {
+    if handler_enabled(Level::Error) {
+        let timestamp = Timestamp::now();
+        trace_format_at(
+            callsite,
+            Level::Error,
+            timestamp,
+            &[],
+            format_args!(
+                "{}",
+                Styled::new(
+                    Level::Error,
+                    timestamp,
+                    callsite,
+                    format_args!("{} at {}:{}", args, callsite.file(), callsite.line())
+                )
+            ),
+        );
+    }
+
+    let action = assert_action();
+    if action == AssertAction::Continue {
+        return;
+    }
+
+    // The main loop won't get to pass on the queued records
+    #[cfg(feature = "deferred")]
+    crate::drain_to_handlers();
+    flush();
+
+    match action {
+        // The panic handler of the crate traces the panic message as well, which would trace the
+        // failure a second time
+        #[cfg(feature = "panic-handler")]
+        AssertAction::Panic => panic!("assertion failed, traced above"),
+        #[cfg(not(feature = "panic-handler"))]
+        AssertAction::Panic => panic!("{}", args),
+        _ => loop {
+            core::hint::spin_loop();
+        },
+    }
+}
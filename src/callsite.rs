@@ -0,0 +1,56 @@
+/// Static information about where a trace record was emitted. The trace macros create one of these
+/// per invocation
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Callsite {
+    target: &'static str,
+    module_path: &'static str,
+    file: &'static str,
+    line: u32,
+}
+
+impl Callsite {
+    pub const fn new(
+        target: &'static str,
+        module_path: &'static str,
+        file: &'static str,
+        line: u32,
+    ) -> Self {
+        Self {
+            target,
+            module_path,
+            file,
+            line,
+        }
+    }
+
+    /// Target used for filtering. Defaults to the module path of the trace call, and is empty for
+    /// records traced with [`trace_format`](crate::trace_format)
+    pub const fn target(&self) -> &'static str {
+        self.target
+    }
+
+    /// Module path of the trace call. Empty for records traced with
+    /// [`trace_format`](crate::trace_format)
+    pub const fn module_path(&self) -> &'static str {
+        self.module_path
+    }
+
+    pub const fn file(&self) -> &'static str {
+        self.file
+    }
+
+    pub const fn line(&self) -> u32 {
+        self.line
+    }
+}
+
+/// Creates a static `Callsite` with a target for the location of the outermost macro invocation
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_callsite {
+    ($target:expr) => {{
+        static CALLSITE: $crate::Callsite =
+            $crate::Callsite::new($target, module_path!(), file!(), line!());
+        &CALLSITE
+    }};
+}
@@ -0,0 +1,23 @@
+//! Identification of the core a trace is made on, for state that is kept per core, such as the
+//! reentrancy guard
+
+// Defined by `#[trace_core_id]`. The weak default always returns 0
+unsafe extern "C" {
+    fn _on_trace_core_id() -> u32;
+}
+
+/// Number of cores with state of their own. Cores with a higher ID share the state of the last one
+pub const MAX_CORES: usize = 2;
+
+/// ID of the core the caller runs on, read from the source registered with `#[trace_core_id]`.
+/// Without one, every trace is made on core 0:
+///
+/// ```ignore
+/// #[trace_core_id]
+/// fn core_id() -> u32 {
+///     SIO.cpuid().read()
+/// }
+/// ```
+pub fn core_id() -> usize {
+    usize::min(unsafe { _on_trace_core_id() } as usize, MAX_CORES - 1)
+}
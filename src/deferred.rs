@@ -0,0 +1,243 @@
+//! Queue moving the handling of records out of interrupts. Records are copied into the queue when
+//! traced, and passed on when the main loop or a low priority task drains the queue
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{
+        AtomicBool, AtomicUsize, Ordering::Acquire, Ordering::Relaxed, Ordering::Release,
+    },
+};
+
+use crate::{Callsite, Level, Record, Timestamp, TraceHandler};
+
+/// Number of records held by the queue of the `deferred` feature
+pub const DEFERRED_QUEUE_LENGTH: usize = 16;
+
+/// Queue of the `deferred` feature, which all records are pushed to instead of being passed on to
+/// the handlers
+#[cfg(feature = "deferred")]
+pub(crate) static DEFERRED: DeferredQueue<DEFERRED_QUEUE_LENGTH> = DeferredQueue::new();
+
+/// Pass the records queued with the `deferred` feature on to `f`, oldest first. Returns the number
+/// of records passed on. Call it from the main loop or a low priority task, and pass the records
+/// on to the real handler:
+///
+/// ```ignore
+/// loop {
+///     trace::drain(|record| UART.log(record));
+/// }
+/// ```
+#[cfg(feature = "deferred")]
+pub fn drain(f: impl FnMut(&Record)) -> usize {
+    DEFERRED.drain(f)
+}
+
+/// Pass the records queued with the `deferred` feature on to the trace handler and the sinks, as
+/// they would have been when traced without it. Returns the number of records passed on
+#[cfg(feature = "deferred")]
+pub fn drain_to_handlers() -> usize {
+    DEFERRED.drain(crate::handler::dispatch_now)
+}
+
+/// A stored record. Messages longer than the entry length are cut and end with the truncation
+/// marker, and longer frames in interned mode are dropped
+struct Entry<const LENGTH: usize> {
+    level: Level,
+    timestamp: Timestamp,
+    callsite: Callsite,
+    length: usize,
+    bytes: [u8; LENGTH],
+}
+
+impl<const LENGTH: usize> Entry<LENGTH> {
+    const fn new() -> Self {
+        Self {
+            level: Level::Debug,
+            timestamp: Timestamp::from_ticks(0),
+            callsite: Callsite::new("", "", "", 0),
+            length: 0,
+            bytes: [0; LENGTH],
+        }
+    }
+
+    fn record(&self) -> Record<'_> {
+        Record::stored(
+            self.level,
+            self.timestamp,
+            &self.callsite,
+            &self.bytes[..self.length],
+        )
+    }
+}
+
+struct Slot<const LENGTH: usize> {
+    // Position the slot is ready for. Equal to the position when it can be written, and one past
+    // it when it has been written and can be read
+    sequence: AtomicUsize,
+    entry: UnsafeCell<Entry<LENGTH>>,
+}
+
+/// A lock-free queue of up to `N` records, each up to `LENGTH` bytes long. `N` must be a power
+/// of two. Any number of interrupts and tasks can push records, which never waits, while a single
+/// reader at a time drains it. It can be registered with `#[trace_handler]`, `set_handler` or as a
+/// sink:
+///
+/// ```ignore
+/// #[trace_handler]
+/// static QUEUE: DeferredQueue<16> = DeferredQueue::new();
+///
+/// loop {
+///     QUEUE.drain(|record| UART.log(record));
+/// }
+/// ```
+///
+/// Records pushed while the queue is full are dropped, and counted by [`DeferredQueue::dropped`].
+/// The fields of records are not kept
+pub struct DeferredQueue<const N: usize, const LENGTH: usize = 128> {
+    slots: [Slot<LENGTH>; N],
+    // Next position to write
+    head: AtomicUsize,
+    // Next position to read. Only changed while draining
+    tail: AtomicUsize,
+    draining: AtomicBool,
+    dropped: AtomicUsize,
+}
+
+// An entry is only written by the writer that claimed its position, and only read once its sequence
+// marks it as written
+unsafe impl<const N: usize, const LENGTH: usize> Sync for DeferredQueue<N, LENGTH> {}
+
+impl<const N: usize, const LENGTH: usize> Default for DeferredQueue<N, LENGTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const LENGTH: usize> DeferredQueue<N, LENGTH> {
+    pub const fn new() -> Self {
+        // Positions wrap around, which only keeps mapping to the same slots for powers of two
+        assert!(
+            N.is_power_of_two(),
+            "the queue length must be a power of two"
+        );
+
+        let mut slots = [const {
+            Slot {
+                sequence: AtomicUsize::new(0),
+                entry: UnsafeCell::new(Entry::new()),
+            }
+        }; N];
+        let mut index = 0;
+        while index < N {
+            slots[index].sequence = AtomicUsize::new(index);
+            index += 1;
+        }
+
+        Self {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            draining: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Copy a record into the queue. Returns false if it was dropped because the queue is full
+    pub fn push(&self, record: &Record) -> bool {
+        if !record.can_store(LENGTH) {
+            self.dropped.fetch_add(1, Relaxed);
+            return false;
+        }
+
+        let mut position = self.head.load(Relaxed);
+        let slot = loop {
+            let slot = &self.slots[position % N];
+            let sequence = slot.sequence.load(Acquire);
+            match sequence.wrapping_sub(position) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                ) {
+                    Ok(_) => break slot,
+                    Err(head) => position = head,
+                },
+                // Not read yet since the last time around
+                difference if difference < 0 => {
+                    self.dropped.fetch_add(1, Relaxed);
+                    return false;
+                }
+                // Claimed by another writer
+                _ => position = self.head.load(Relaxed),
+            }
+        };
+
+        // The position was claimed above, so no one else accesses the entry until it is published
+        let entry = unsafe { &mut *slot.entry.get() };
+        entry.level = record.level();
+        entry.timestamp = record.timestamp();
+        entry.callsite = *record.callsite();
+        entry.length = record.store(&mut entry.bytes);
+        slot.sequence.store(position.wrapping_add(1), Release);
+        true
+    }
+
+    /// Pass the queued records on to `f`, oldest first, and remove them. Returns the number of
+    /// records passed on, which is 0 if the queue is already being drained. Records pushed while
+    /// draining, such as by `f` itself, and records still being written are left for the next call
+    pub fn drain(&self, mut f: impl FnMut(&Record)) -> usize {
+        if self
+            .draining
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            return 0;
+        }
+
+        let mut count = 0;
+        let mut position = self.tail.load(Relaxed);
+        let end = self.head.load(Relaxed);
+        while position != end {
+            let slot = &self.slots[position % N];
+            if slot.sequence.load(Acquire) != position.wrapping_add(1) {
+                break;
+            }
+
+            // Published by its writer, and not written again until the sequence is moved on
+            f(&unsafe { &*slot.entry.get() }.record());
+            slot.sequence.store(position.wrapping_add(N), Release);
+            position = position.wrapping_add(1);
+            self.tail.store(position, Relaxed);
+            count += 1;
+        }
+
+        self.draining.store(false, Release);
+        count
+    }
+
+    /// Number of queued records, including those still being written
+    pub fn len(&self) -> usize {
+        usize::min(
+            self.head
+                .load(Relaxed)
+                .wrapping_sub(self.tail.load(Relaxed)),
+            N,
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of records dropped since startup
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Relaxed)
+    }
+}
+
+impl<const N: usize, const LENGTH: usize> TraceHandler for DeferredQueue<N, LENGTH> {
+    fn log(&self, record: &Record) {
+        self.push(record);
+    }
+}
@@ -0,0 +1,202 @@
+//! Runtime filtering of trace records. Records are filtered by level and target before they are
+//! formatted, so disabled records only cost a few atomic loads.
+
+use core::{
+    cell::UnsafeCell,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU8, Ordering::Acquire, Ordering::Relaxed, Ordering::Release},
+};
+
+use crate::Level;
+
+/// Maximum number of directives of a filter. Further directives are ignored
+pub const MAX_FILTER_DIRECTIVES: usize = 16;
+
+/// Threshold that no level passes, used for `off` directives. Thresholds are severities, as the
+/// values of the levels are not in order
+const OFF: u8 = Level::Fatal.severity() + 1;
+
+// States of the directive table of a filter
+const UNPARSED: u8 = 0;
+const PARSING: u8 = 1;
+const PARSED: u8 = 2;
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Trace as u8);
+static FILTER: AtomicPtr<Filter> = AtomicPtr::new(ptr::null_mut());
+
+/// Set the most verbose level that is traced at runtime. Trace macros with a less severe level
+/// return before formatting their arguments. Defaults to `Level::Trace`, tracing everything
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Relaxed);
+}
+
+/// Get the most verbose level that is traced at runtime
+pub fn max_level() -> Level {
+    Level::try_from(MAX_LEVEL.load(Relaxed)).unwrap_or(Level::Trace)
+}
+
+/// Check if a level passes the runtime level filter
+#[inline]
+pub fn enabled(level: Level) -> bool {
+    level >= max_level()
+}
+
+/// Set a per target filter. Targets that no directive matches fall back to the max level. The spec
+/// is parsed here, so that traces only look up the parsed directives
+pub fn set_filter(filter: &'static Filter) {
+    filter.parse();
+    FILTER.store(ptr::from_ref(filter).cast_mut(), Release);
+}
+
+/// Remove the per target filter, so that all targets use the max level
+pub fn clear_filter() {
+    FILTER.store(ptr::null_mut(), Release);
+}
+
+/// Check if a level passes the runtime filter for a target
+#[inline]
+pub fn target_enabled(level: Level, target: &str) -> bool {
+    let filter = FILTER.load(Acquire);
+    if filter.is_null() {
+        return enabled(level);
+    }
+
+    // Filters can only be set from `&'static Filter`
+    match unsafe { &*filter }.threshold(target) {
+        Some(threshold) => level.severity() >= threshold,
+        None => enabled(level),
+    }
+}
+
+/// A filter spec with comma separated `target=level` directives, such as
+/// `net=debug,storage=warning,*=info`.
+///
+/// A directive matches a target that is equal to its name, or that is a submodule of it, so `net`
+/// matches both `net` and `net::tcp`. If multiple directives match, the longest name is used. `*`
+/// matches every target. Valid levels are `trace`, `debug`, `info`, `warning`, `error`, `fatal` and
+/// `off`. Invalid directives are ignored, as are directives after the first
+/// [`MAX_FILTER_DIRECTIVES`]. The spec is parsed once, when the filter is first used
+pub struct Filter {
+    spec: &'static str,
+    state: AtomicU8,
+    directives: UnsafeCell<Directives>,
+}
+
+// The directives are only written while parsing, before the state marks them as parsed
+unsafe impl Sync for Filter {}
+
+#[derive(Clone, Copy)]
+struct Directive {
+    name: &'static str,
+    threshold: u8,
+}
+
+struct Directives {
+    directives: [Directive; MAX_FILTER_DIRECTIVES],
+    count: usize,
+}
+
+impl Filter {
+    pub const fn new(spec: &'static str) -> Self {
+        Self {
+            spec,
+            state: AtomicU8::new(UNPARSED),
+            directives: UnsafeCell::new(Directives {
+                directives: [Directive {
+                    name: "",
+                    threshold: OFF,
+                }; MAX_FILTER_DIRECTIVES],
+                count: 0,
+            }),
+        }
+    }
+
+    /// Check if a level passes this filter for a target. Returns `None` if no directive matches
+    pub fn enabled(&self, level: Level, target: &str) -> Option<bool> {
+        self.parse();
+        self.threshold(target)
+            .map(|threshold| level.severity() >= threshold)
+    }
+
+    /// Parse the spec into the directive table, unless that has already been done. Like the other
+    /// locks of this crate, this never waits. While another context is parsing, no directive
+    /// matches until it is done
+    fn parse(&self) {
+        if self
+            .state
+            .compare_exchange(UNPARSED, PARSING, Acquire, Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        // Only this context gets to write the directives, and no one reads them until they are
+        // marked as parsed
+        let directives = unsafe { &mut *self.directives.get() };
+        for directive in self.spec.split(',') {
+            let Some((name, level)) = directive.split_once('=') else {
+                continue;
+            };
+            let Some(threshold) = parse_level(level.trim()) else {
+                continue;
+            };
+            if directives.count == MAX_FILTER_DIRECTIVES {
+                break;
+            }
+
+            directives.directives[directives.count] = Directive {
+                name: name.trim(),
+                threshold,
+            };
+            directives.count += 1;
+        }
+        self.state.store(PARSED, Release);
+    }
+
+    fn directives(&self) -> &[Directive] {
+        if self.state.load(Acquire) != PARSED {
+            return &[];
+        }
+
+        // Not written again once parsed
+        let directives = unsafe { &*self.directives.get() };
+        &directives.directives[..directives.count]
+    }
+
+    fn threshold(&self, target: &str) -> Option<u8> {
+        let mut res = None;
+        let mut most_specific = 0;
+        for directive in self.directives() {
+            // A name is more specific than `*`, and a longer name is more specific than a shorter one
+            let specificity = match directive.name {
+                "*" => 0,
+                name if target
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::")) =>
+                {
+                    name.len() + 1
+                }
+                _ => continue,
+            };
+            if res.is_none() || specificity > most_specific {
+                res = Some(directive.threshold);
+                most_specific = specificity;
+            }
+        }
+        res
+    }
+}
+
+fn parse_level(level: &str) -> Option<u8> {
+    let level = match level {
+        "trace" => Level::Trace,
+        "debug" => Level::Debug,
+        "info" => Level::Info,
+        "warning" | "warn" => Level::Warning,
+        "error" => Level::Error,
+        "fatal" => Level::Fatal,
+        "off" => return Some(OFF),
+        _ => return None,
+    };
+    Some(level.severity())
+}
@@ -0,0 +1,146 @@
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering::Acquire, Ordering::Release},
+};
+
+use crate::{Level, Record, reentrancy, sink};
+
+// Defined by `#[trace_handler]` on a static. The weak defaults enable every level and do nothing
+// on flush
+unsafe extern "C" {
+    fn _on_trace_enabled(level: Level) -> bool;
+    fn _on_trace_flush();
+}
+
+// The reference is stored behind another reference, as a `&dyn TraceHandler` is two words and
+// can't be swapped atomically
+static HANDLER: AtomicPtr<&'static dyn TraceHandler> = AtomicPtr::new(ptr::null_mut());
+
+/// A trace handler with state, as an alternative to a trace handler function. Register a static
+/// implementing it with `#[trace_handler]`:
+///
+/// ```ignore
+/// #[trace_handler]
+/// static HANDLER: Uart = Uart::new();
+/// ```
+pub trait TraceHandler: Sync {
+    /// Handle a record that passed all filters
+    fn log(&self, record: &Record);
+
+    /// Check if records of a level should be handled. Checked before the message is formatted
+    fn enabled(&self, _level: Level) -> bool {
+        true
+    }
+
+    /// Write out any buffered records
+    fn flush(&self) {}
+}
+
+/// Register a trace handler at runtime, replacing any previously registered handler. Records are
+/// passed to the handler registered with `#[trace_handler]` until this is called. The handler is
+/// passed as a reference to a static reference:
+///
+/// ```ignore
+/// static UART_HANDLER: &dyn TraceHandler = &UART;
+/// trace::set_handler(&UART_HANDLER);
+/// ```
+pub fn set_handler(handler: &'static &'static dyn TraceHandler) {
+    HANDLER.store(ptr::from_ref(handler).cast_mut(), Release);
+}
+
+/// Remove the runtime trace handler, falling back to the handler registered with
+/// `#[trace_handler]`
+pub fn clear_handler() {
+    HANDLER.store(ptr::null_mut(), Release);
+}
+
+fn handler() -> Option<&'static dyn TraceHandler> {
+    // Only ever set from `&'static &'static dyn TraceHandler`
+    unsafe { HANDLER.load(Acquire).as_ref().copied() }
+}
+
+/// Pass a record on to the trace handler and the registered sinks, or queue it to be passed on
+/// later with the `deferred` feature
+pub(crate) fn dispatch(record: &Record) {
+    #[cfg(feature = "deferred")]
+    crate::deferred::DEFERRED.push(record);
+    #[cfg(not(feature = "deferred"))]
+    dispatch_now(record);
+}
+
+/// Pass a record on to the trace handler and the registered sinks, unless another trace is already
+/// being handled
+pub(crate) fn dispatch_now(record: &Record) {
+    if !reentrancy::enter() {
+        reentrancy::nested(record);
+        return;
+    }
+
+    dispatch_unguarded(record);
+    reentrancy::exit();
+}
+
+/// Pass a record on to the handlers without checking for nested traces
+pub(crate) fn dispatch_unguarded(record: &Record) {
+    if main_handler_enabled(record.level()) {
+        log(record);
+    }
+    sink::dispatch_sinks(record);
+}
+
+fn log(record: &Record) {
+    // Records are only formatted before streaming when they were queued
+    #[cfg(not(feature = "interned"))]
+    if let Some(handler) = crate::stream::stream_handler() {
+        let args = format_args!("{}", record.message());
+        crate::stream::stream(
+            handler,
+            record.callsite(),
+            record.level(),
+            record.fields(),
+            args,
+        );
+        return;
+    }
+
+    match handler() {
+        Some(handler) => handler.log(record),
+        None => {
+            let bytes = record.bytes();
+            unsafe { crate::_on_trace_record(record.level(), bytes.as_ptr(), bytes.len(), record) };
+        }
+    }
+}
+
+fn main_handler_enabled(level: Level) -> bool {
+    #[cfg(not(feature = "interned"))]
+    if let Some(handler) = crate::stream::stream_handler() {
+        return handler.enabled(level);
+    }
+
+    match handler() {
+        Some(handler) => handler.enabled(level),
+        None => unsafe { _on_trace_enabled(level) },
+    }
+}
+
+/// Check if the trace handler or any sink accepts a level. Always true for trace handler functions.
+/// While a stream handler is registered, it is checked instead of the trace handler
+pub fn handler_enabled(level: Level) -> bool {
+    main_handler_enabled(level) || sink::sinks_enabled(level)
+}
+
+/// Flush the stream handler, the trace handler and all sinks. Does nothing for handlers that don't
+/// implement `flush`
+pub fn flush() {
+    #[cfg(not(feature = "interned"))]
+    if let Some(handler) = crate::stream::stream_handler() {
+        handler.flush();
+    }
+
+    match handler() {
+        Some(handler) => handler.flush(),
+        None => unsafe { _on_trace_flush() },
+    }
+    sink::flush_sinks();
+}
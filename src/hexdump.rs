@@ -0,0 +1,97 @@
+//! Hex dumps of byte buffers, traced as a header followed by one record per row, so that buffers of
+//! any size fit in the format buffer
+
+use core::fmt;
+
+use crate::{Callsite, Level, Styled, TRACE_FORMAT_BUFFER_SIZE, Timestamp, trace_format_at};
+
+/// Number of bytes shown per row of a hex dump
+pub const HEXDUMP_ROW_LENGTH: usize = 16;
+
+/// Length of the text of a row: the offset, the bytes in hex in two halves and the ASCII column
+pub(crate) const ROW_TEXT_LENGTH: usize =
+    9 + 2 + HEXDUMP_ROW_LENGTH * 3 + 2 + HEXDUMP_ROW_LENGTH + 1;
+
+/// Room for the colors, label and line ending of the default style, such as `WARNING: `
+const PREFIX_LENGTH: usize = 32;
+
+// Rows are only split at the row length, so a full row has to fit in the format buffer
+const _: () = assert!(
+    ROW_TEXT_LENGTH + PREFIX_LENGTH <= TRACE_FORMAT_BUFFER_SIZE,
+    "a hex dump row doesn't fit in the format buffer"
+);
+
+/// Trace a header with the label and length of `bytes`, followed by rows of the offset, the bytes in
+/// hex and the bytes as ASCII. Does not check any filters, which is up to the caller, as done by
+/// [`trace_hexdump!`](crate::trace_hexdump)
+pub fn trace_hexdump_at(callsite: &Callsite, level: Level, label: &str, bytes: &[u8]) {
+    // The rows are part of the same dump, so they share the timestamp of the header
+    let timestamp = Timestamp::now();
+    trace_format_at(
+        callsite,
+        level,
+        timestamp,
+        &[],
+        format_args!(
+            "{}",
+            Styled::new(
+                level,
+                timestamp,
+                callsite,
+                format_args!("{} ({} bytes)", label, bytes.len())
+            )
+        ),
+    );
+
+    for (row, chunk) in bytes.chunks(HEXDUMP_ROW_LENGTH).enumerate() {
+        let row = Row {
+            offset: row * HEXDUMP_ROW_LENGTH,
+            bytes: chunk,
+        };
+        trace_format_at(
+            callsite,
+            level,
+            timestamp,
+            &[],
+            format_args!(
+                "{}",
+                Styled::new(level, timestamp, callsite, format_args!("{}", row))
+            ),
+        );
+    }
+}
+
+/// A row of a hex dump, such as
+/// `00000010  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0d 0a 00  |Hello, world!...|`
+pub(crate) struct Row<'a> {
+    pub(crate) offset: usize,
+    pub(crate) bytes: &'a [u8],
+}
+
+impl fmt::Display for Row<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x} ", self.offset)?;
+        for i in 0..HEXDUMP_ROW_LENGTH {
+            // Halves are separated by an extra space
+            if i % (HEXDUMP_ROW_LENGTH / 2) == 0 {
+                f.write_str(" ")?;
+            }
+            // Short rows are padded to keep the ASCII column aligned
+            match self.bytes.get(i) {
+                Some(byte) => write!(f, "{:02x} ", byte)?,
+                None => f.write_str("   ")?,
+            }
+        }
+
+        f.write_str(" |")?;
+        for &byte in self.bytes {
+            let character = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            write!(f, "{}", character)?;
+        }
+        f.write_str("|")
+    }
+}
@@ -0,0 +1,738 @@
+//! Deferred binary tracing. With the `interned` feature enabled, the trace macros no longer format
+//! on the target. Instead, each format string is placed in the `.trace_strings` linker section at
+//! compile time, and only its address together with the raw argument bytes is handed to the
+//! trace handler as a [`Frame`]. The text is then reconstructed on the host with a [`Decoder`].
+//!
+//! A frame is laid out as
+//!
+//! ```text
+//! [level: u8][index: LEB128][argument]*
+//! ```
+//!
+//! where each argument is a one byte tag followed by its payload. An index of `0` means that no
+//! format string was interned, and that the frame carries a single string argument with the
+//! already formatted text. This is what [`trace_format`](crate::trace_format) emits.
+
+use core::fmt::{self, Write};
+
+#[cfg(feature = "interned")]
+use crate::{Callsite, Field, Record, Timestamp};
+use crate::{Level, TRACE_FORMAT_BUFFER_SIZE, TraceString};
+
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_F32: u8 = 2;
+const TAG_F64: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_STR: u8 = 6;
+
+/// Index used for frames that carry already formatted text instead of an interned format string
+pub const RAW_INDEX: usize = 0;
+
+/// Copies a format string into a null terminated byte array. Used by the trace macros to place
+/// format strings in the `.trace_strings` section
+pub const fn intern<const N: usize>(format: &str) -> [u8; N] {
+    let bytes = format.as_bytes();
+    assert!(bytes.len() + 1 == N);
+
+    let mut res = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        res[i] = bytes[i];
+        i += 1;
+    }
+    res
+}
+
+/// Places a format string in the `.trace_strings` section and evaluates to its address, which is
+/// used as the index of the format string
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_intern {
+    ($fmt:expr) => {{
+        const FORMAT: &str = $fmt;
+        #[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__trace_strings"))]
+        #[cfg_attr(not(target_os = "macos"), unsafe(link_section = ".trace_strings"))]
+        static INTERNED: [u8; FORMAT.len() + 1] = $crate::interned::intern(FORMAT);
+        &INTERNED as *const _ as usize
+    }};
+}
+
+/// A binary trace record, consisting of a level, the index of an interned format string and the
+/// encoded arguments. Arguments that do not fit in the frame are dropped, and strings are cut of
+/// at the last character boundary that fits
+pub struct Frame {
+    level: Level,
+    length: usize,
+    buffer: [u8; TRACE_FORMAT_BUFFER_SIZE],
+}
+
+impl Frame {
+    pub fn new(level: Level, index: usize) -> Self {
+        let mut res = Self {
+            level,
+            length: 0,
+            buffer: [0; TRACE_FORMAT_BUFFER_SIZE],
+        };
+        res.buffer[0] = level as u8;
+        res.length = 1;
+        res.write_varint(index as u64);
+        res
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+
+    /// Encode an argument into the frame
+    pub fn push<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode(self);
+    }
+
+    /// Encode an argument shown as bits, such as with `{:x}`, so that negative numbers decode in
+    /// two's complement
+    pub fn push_bits<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode_bits(self);
+    }
+
+    pub fn push_unsigned(&mut self, value: u64) {
+        if self.fits(1 + varint_length(value)) {
+            self.write_byte(TAG_UNSIGNED);
+            self.write_varint(value);
+        }
+    }
+
+    pub fn push_signed(&mut self, value: i64) {
+        // Zigzag encode to keep small negative numbers short
+        let value = ((value << 1) ^ (value >> 63)) as u64;
+        if self.fits(1 + varint_length(value)) {
+            self.write_byte(TAG_SIGNED);
+            self.write_varint(value);
+        }
+    }
+
+    pub fn push_f32(&mut self, value: f32) {
+        if self.fits(5) {
+            self.write_byte(TAG_F32);
+            self.write_bytes(&value.to_le_bytes());
+        }
+    }
+
+    pub fn push_f64(&mut self, value: f64) {
+        if self.fits(9) {
+            self.write_byte(TAG_F64);
+            self.write_bytes(&value.to_le_bytes());
+        }
+    }
+
+    pub fn push_bool(&mut self, value: bool) {
+        if self.fits(2) {
+            self.write_byte(TAG_BOOL);
+            self.write_byte(value as u8);
+        }
+    }
+
+    pub fn push_char(&mut self, value: char) {
+        if self.fits(1 + varint_length(value as u64)) {
+            self.write_byte(TAG_CHAR);
+            self.write_varint(value as u64);
+        }
+    }
+
+    pub fn push_str(&mut self, value: &str) {
+        // The tag and the length go first. The length is bounded by what remains of the buffer
+        if !self.fits(2) {
+            return;
+        }
+
+        let space = self.remaining() - 1;
+        let mut length = usize::min(value.len(), space - varint_length(space as u64));
+        while !value.is_char_boundary(length) {
+            length -= 1;
+        }
+
+        self.write_byte(TAG_STR);
+        self.write_varint(length as u64);
+        self.write_bytes(&value.as_bytes()[..length]);
+    }
+
+    /// Format text straight into the frame as a string argument, cut like a `TraceString` if it
+    /// doesn't fit. The length goes first, so it takes as many bytes as the length of the longest
+    /// text that fits
+    #[cfg(feature = "interned")]
+    pub(crate) fn push_formatted(&mut self, args: fmt::Arguments) {
+        if !self.fits(2) {
+            return;
+        }
+
+        let space = self.remaining() - 1;
+        let width = varint_length(space as u64);
+        let start = self.length + 1 + width;
+        let mut text = FrameText {
+            buffer: &mut self.buffer[start..],
+            length: 0,
+            truncated: false,
+        };
+        let _ = fmt::write(&mut text, args);
+        let length = text.length;
+
+        self.write_byte(TAG_STR);
+        self.write_padded_varint(length as u64, width);
+        self.length += length;
+    }
+
+    /// Pass the frame on to the trace handler. Fields are not encoded in the frame, but passed to
+    /// the handler as part of the record
+    #[cfg(feature = "interned")]
+    pub fn emit(&self, callsite: &Callsite, fields: &[Field]) {
+        self.emit_at(Timestamp::now(), callsite, fields);
+    }
+
+    /// Pass the frame on to the trace handler with the time it was traced at
+    #[cfg(feature = "interned")]
+    pub(crate) fn emit_at(&self, timestamp: Timestamp, callsite: &Callsite, fields: &[Field]) {
+        crate::dispatch(&Record::new(
+            self.level,
+            timestamp,
+            callsite,
+            self.as_bytes(),
+            fields,
+        ));
+    }
+
+    fn fits(&self, length: usize) -> bool {
+        self.remaining() >= length
+    }
+
+    fn remaining(&self) -> usize {
+        TRACE_FORMAT_BUFFER_SIZE - self.length
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.buffer[self.length] = byte;
+        self.length += 1;
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer[self.length..self.length + bytes.len()].copy_from_slice(bytes);
+        self.length += bytes.len();
+    }
+
+    /// Write a varint that takes exactly `width` bytes, which decodes like the shortest one
+    #[cfg(feature = "interned")]
+    fn write_padded_varint(&mut self, mut value: u64, width: usize) {
+        for i in 1..=width {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            self.write_byte(if i < width { byte | 0x80 } else { byte });
+        }
+    }
+
+    fn write_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.write_byte(byte);
+                return;
+            }
+            self.write_byte(byte | 0x80);
+        }
+    }
+}
+
+/// Text formatted into the part of a frame after the length of a string argument
+#[cfg(feature = "interned")]
+struct FrameText<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+    truncated: bool,
+}
+
+#[cfg(feature = "interned")]
+impl Write for FrameText<'_> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        // Anything after the marker is dropped
+        if !self.truncated {
+            self.truncated = !crate::append_or_truncate(self.buffer, &mut self.length, string);
+        }
+        Ok(())
+    }
+}
+
+fn varint_length(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    usize::max(1, bits.div_ceil(7))
+}
+
+/// Types that can be passed as arguments to the trace macros in interned mode
+pub trait Encode {
+    fn encode(&self, frame: &mut Frame);
+
+    /// Encode the value for a placeholder showing its bits, such as `{:x}`. Signed integers are
+    /// encoded as the unsigned integer of the same width
+    fn encode_bits(&self, frame: &mut Frame) {
+        self.encode(frame);
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, frame: &mut Frame) {
+        (**self).encode(frame);
+    }
+
+    fn encode_bits(&self, frame: &mut Frame) {
+        (**self).encode_bits(frame);
+    }
+}
+
+macro_rules! impl_encode {
+    ($method:ident, $target:ty, $($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, frame: &mut Frame) {
+                    frame.$method(*self as $target);
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_encode_signed {
+    ($($ty:ty => $unsigned:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, frame: &mut Frame) {
+                    frame.push_signed(*self as i64);
+                }
+
+                fn encode_bits(&self, frame: &mut Frame) {
+                    frame.push_unsigned(*self as $unsigned as u64);
+                }
+            }
+        )*
+    };
+}
+
+impl_encode!(push_unsigned, u64, u8, u16, u32, u64, usize);
+impl_encode_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, isize => usize);
+impl_encode!(push_f32, f32, f32);
+impl_encode!(push_f64, f64, f64);
+
+impl Encode for bool {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push_bool(*self);
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push_char(*self);
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push_str(self);
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DecodeError {
+    /// The frame ended before a complete value could be read
+    UnexpectedEnd,
+    /// The frame starts with a byte that is not a valid level
+    InvalidLevel,
+    /// An argument has an unknown tag or an invalid payload
+    InvalidArgument,
+    /// The index does not point to a string in the section
+    UnknownIndex,
+    /// The format string references more arguments than the frame carries
+    MissingArgument,
+    /// The format string contains a placeholder that the decoder can not render
+    UnsupportedFormat,
+    /// The output writer returned an error
+    Write,
+}
+
+impl From<fmt::Error> for DecodeError {
+    fn from(_: fmt::Error) -> Self {
+        DecodeError::Write
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Argument<'a> {
+    Unsigned(u64),
+    Signed(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(&'a str),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&first, rest) = self.bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+        self.bytes = rest;
+        Ok(first)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < length {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (first, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(first)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut res = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 {
+                return Err(DecodeError::InvalidArgument);
+            }
+            res |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(res);
+            }
+            shift += 7;
+        }
+    }
+
+    fn argument(&mut self) -> Result<Argument<'a>, DecodeError> {
+        Ok(match self.byte()? {
+            TAG_UNSIGNED => Argument::Unsigned(self.varint()?),
+            TAG_SIGNED => {
+                let value = self.varint()?;
+                Argument::Signed(((value >> 1) as i64) ^ -((value & 1) as i64))
+            }
+            TAG_F32 => Argument::F32(f32::from_le_bytes(
+                self.bytes(4)?.try_into().unwrap_or_default(),
+            )),
+            TAG_F64 => Argument::F64(f64::from_le_bytes(
+                self.bytes(8)?.try_into().unwrap_or_default(),
+            )),
+            TAG_BOOL => Argument::Bool(self.byte()? != 0),
+            TAG_CHAR => Argument::Char(
+                u32::try_from(self.varint()?)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(DecodeError::InvalidArgument)?,
+            ),
+            TAG_STR => {
+                let length = self.varint()? as usize;
+                Argument::Str(
+                    core::str::from_utf8(self.bytes(length)?)
+                        .map_err(|_| DecodeError::InvalidArgument)?,
+                )
+            }
+            _ => return Err(DecodeError::InvalidArgument),
+        })
+    }
+}
+
+/// Host side decoder for frames. Takes the contents of the `.trace_strings` section of the traced
+/// binary along with the address the section is loaded at
+pub struct Decoder<'a> {
+    section: &'a [u8],
+    address: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub const fn new(section: &'a [u8], address: usize) -> Self {
+        Self { section, address }
+    }
+
+    /// Look up the format string for an index
+    pub fn format_string(&self, index: usize) -> Option<&'a str> {
+        let offset = index.checked_sub(self.address)?;
+        let bytes = self.section.get(offset..)?;
+        let end = bytes.iter().position(|&byte| byte == 0)?;
+        core::str::from_utf8(&bytes[..end]).ok()
+    }
+
+    /// Decode a frame and write the reconstructed text to `out`. Returns the level of the frame
+    pub fn decode(&self, frame: &[u8], out: &mut impl Write) -> Result<Level, DecodeError> {
+        let mut reader = Reader { bytes: frame };
+        let level = Level::try_from(reader.byte()?).map_err(|_| DecodeError::InvalidLevel)?;
+        let index = reader.varint()? as usize;
+
+        let mut arguments = [Argument::Bool(false); 32];
+        let mut count = 0;
+        while !reader.bytes.is_empty() {
+            if count == arguments.len() {
+                return Err(DecodeError::UnsupportedFormat);
+            }
+            arguments[count] = reader.argument()?;
+            count += 1;
+        }
+        let arguments = &arguments[..count];
+
+        if index == RAW_INDEX {
+            match arguments {
+                [Argument::Str(string)] => out.write_str(string)?,
+                _ => return Err(DecodeError::InvalidArgument),
+            }
+            return Ok(level);
+        }
+
+        let format = self.format_string(index).ok_or(DecodeError::UnknownIndex)?;
+        render(format, arguments, out)?;
+        Ok(level)
+    }
+}
+
+fn render(format: &str, arguments: &[Argument], out: &mut impl Write) -> Result<(), DecodeError> {
+    let mut next = 0;
+    let mut rest = format;
+    while let Some(position) = rest.find(['{', '}']) {
+        out.write_str(&rest[..position])?;
+        let tail = &rest[position..];
+
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            out.write_str(&tail[..1])?;
+            rest = &tail[2..];
+            continue;
+        }
+        if tail.starts_with('}') {
+            return Err(DecodeError::UnsupportedFormat);
+        }
+
+        let end = tail.find('}').ok_or(DecodeError::UnsupportedFormat)?;
+        let placeholder = &tail[1..end];
+        let (position, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+        let argument = if position.is_empty() {
+            next += 1;
+            next - 1
+        } else {
+            position
+                .parse::<usize>()
+                .map_err(|_| DecodeError::UnsupportedFormat)?
+        };
+
+        let argument = arguments
+            .get(argument)
+            .ok_or(DecodeError::MissingArgument)?;
+        render_argument(*argument, &Spec::parse(spec)?, out)?;
+        rest = &tail[end + 1..];
+    }
+    out.write_str(rest)?;
+    Ok(())
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Parsed `[[fill]align][sign]['#']['0'][width]['.' precision][type]` format specification
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: char,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Result<Self, DecodeError> {
+        let mut res = Spec {
+            fill: ' ',
+            align: None,
+            plus: false,
+            alternate: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            kind: ' ',
+        };
+
+        let to_align = |c| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+
+        let mut chars = spec.chars().peekable();
+        let mut lookahead = spec.chars().skip(1);
+        if let Some(align) = lookahead.next().and_then(to_align) {
+            res.fill = chars.next().unwrap_or(' ');
+            res.align = Some(align);
+            chars.next();
+        } else if let Some(align) = chars.peek().copied().and_then(to_align) {
+            res.align = Some(align);
+            chars.next();
+        }
+
+        if chars.next_if_eq(&'+').is_some() {
+            res.plus = true;
+        }
+        if chars.next_if_eq(&'#').is_some() {
+            res.alternate = true;
+        }
+        if chars.next_if_eq(&'0').is_some() {
+            res.zero = true;
+        }
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            res.width = res.width * 10 + digit as usize - '0' as usize;
+        }
+        if chars.next_if_eq(&'.').is_some() {
+            let mut precision = 0;
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                precision = precision * 10 + digit as usize - '0' as usize;
+            }
+            res.precision = Some(precision);
+        }
+        if let Some(kind) = chars.next() {
+            res.kind = kind;
+        }
+        if chars.next().is_some() || !matches!(res.kind, ' ' | '?' | 'x' | 'X' | 'o' | 'b' | 'e') {
+            return Err(DecodeError::UnsupportedFormat);
+        }
+        Ok(res)
+    }
+}
+
+fn render_argument(
+    argument: Argument,
+    spec: &Spec,
+    out: &mut impl Write,
+) -> Result<(), DecodeError> {
+    let mut body = TraceString::new();
+    let mut negative = false;
+    let mut numeric = true;
+
+    let radix_prefix = match spec.kind {
+        'x' | 'X' => "0x",
+        'o' => "0o",
+        'b' => "0b",
+        _ => "",
+    };
+
+    match argument {
+        Argument::Unsigned(value) => write_integer(&mut body, value, spec.kind)?,
+        Argument::Signed(value) => {
+            negative = value < 0;
+            write_integer(&mut body, value.unsigned_abs(), spec.kind)?;
+        }
+        Argument::F32(value) => {
+            negative = value.is_sign_negative();
+            write_float(&mut body, if negative { -value } else { value }, spec)?;
+        }
+        Argument::F64(value) => {
+            negative = value.is_sign_negative();
+            write_float(&mut body, if negative { -value } else { value }, spec)?;
+        }
+        Argument::Bool(value) => {
+            numeric = false;
+            write!(body, "{}", value)?;
+        }
+        Argument::Char(value) => {
+            numeric = false;
+            match spec.kind {
+                '?' => write!(body, "{:?}", value)?,
+                _ => write!(body, "{}", value)?,
+            }
+        }
+        Argument::Str(value) => {
+            numeric = false;
+            match (spec.kind, spec.precision) {
+                ('?', _) => write!(body, "{:?}", value)?,
+                (_, Some(precision)) => write!(body, "{:.*}", precision, value)?,
+                _ => write!(body, "{}", value)?,
+            }
+        }
+    }
+
+    let sign = match (negative, spec.plus) {
+        (true, _) => "-",
+        (false, true) => "+",
+        _ => "",
+    };
+    let prefix = if numeric && spec.alternate {
+        radix_prefix
+    } else {
+        ""
+    };
+    let body = body.to_string();
+    let length = sign.len() + prefix.len() + body.chars().count();
+    let padding = spec.width.saturating_sub(length);
+
+    if numeric && spec.zero {
+        out.write_str(sign)?;
+        out.write_str(prefix)?;
+        write_fill(out, '0', padding)?;
+        out.write_str(body)?;
+        return Ok(());
+    }
+
+    let align = spec
+        .align
+        .unwrap_or(if numeric { Align::Right } else { Align::Left });
+    let (before, after) = match align {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+
+    write_fill(out, spec.fill, before)?;
+    out.write_str(sign)?;
+    out.write_str(prefix)?;
+    out.write_str(body)?;
+    write_fill(out, spec.fill, after)?;
+    Ok(())
+}
+
+fn write_integer(out: &mut impl Write, value: u64, kind: char) -> fmt::Result {
+    match kind {
+        'x' => write!(out, "{:x}", value),
+        'X' => write!(out, "{:X}", value),
+        'o' => write!(out, "{:o}", value),
+        'b' => write!(out, "{:b}", value),
+        'e' => write!(out, "{:e}", value),
+        _ => write!(out, "{}", value),
+    }
+}
+
+fn write_float<T: fmt::Display + fmt::Debug + fmt::LowerExp>(
+    out: &mut impl Write,
+    value: T,
+    spec: &Spec,
+) -> Result<(), DecodeError> {
+    match (spec.kind, spec.precision) {
+        ('e', Some(precision)) => write!(out, "{:.*e}", precision, value)?,
+        ('e', None) => write!(out, "{:e}", value)?,
+        (' ' | '?', Some(precision)) => write!(out, "{:.*}", precision, value)?,
+        ('?', None) => write!(out, "{:?}", value)?,
+        (' ', None) => write!(out, "{}", value)?,
+        _ => return Err(DecodeError::UnsupportedFormat),
+    }
+    Ok(())
+}
+
+fn write_fill(out: &mut impl Write, fill: char, count: usize) -> fmt::Result {
+    for _ in 0..count {
+        out.write_char(fill)?;
+    }
+    Ok(())
+}
@@ -7,32 +7,190 @@
 #[cfg(test)]
 mod tests;
 
+mod assert;
+mod callsite;
+mod core_id;
+pub mod deferred;
+mod filter;
+mod handler;
+pub mod hexdump;
+pub mod interned;
+#[cfg(feature = "log")]
+pub mod log_bridge;
+// Tests run with std, which has its own panic handler, but still check the tracing of panics where
+// the trace tests are built
+#[cfg(any(feature = "panic-handler", test))]
+#[cfg_attr(not(feature = "panic-handler"), allow(dead_code))]
+mod panic;
+mod record;
+pub mod reentrancy;
+pub mod ring_buffer;
+mod sink;
+#[cfg(not(feature = "interned"))]
+mod stream;
+mod style;
+mod throttle;
+mod timestamp;
+#[cfg(feature = "tracing")]
+pub mod tracing_bridge;
 mod weak_on_trace;
 
-pub use trace_macro::trace_handler;
+pub use assert::{AssertAction, assert_action, assert_failed, set_assert_action};
+pub use callsite::Callsite;
+pub use core_id::{MAX_CORES, core_id};
+pub use deferred::DeferredQueue;
+#[cfg(feature = "deferred")]
+pub use deferred::{drain, drain_to_handlers};
+pub use filter::{
+    Filter, MAX_FILTER_DIRECTIVES, clear_filter, enabled, max_level, set_filter, set_max_level,
+    target_enabled,
+};
+pub(crate) use handler::dispatch;
+pub use handler::{TraceHandler, clear_handler, flush, handler_enabled, set_handler};
+pub use hexdump::trace_hexdump_at;
+pub use record::{Field, Record, Value};
+pub use reentrancy::{ReentrancyPolicy, nested_dropped, reentrancy_policy, set_reentrancy_policy};
+pub use ring_buffer::RingBuffer;
+pub use sink::{MAX_SINKS, Sink, SinkError, add_sink, remove_sink};
+#[cfg(not(feature = "interned"))]
+pub use stream::{StreamHandler, clear_stream_handler, set_stream_handler};
+pub use style::{Color, Part, Style, Styled, clear_style, set_style, style};
+pub use throttle::Throttle;
+pub use timestamp::{Timestamp, Uptime};
+#[doc(hidden)]
+pub use trace_macro::__trace_encode;
+pub use trace_macro::{trace_core_id, trace_handler, trace_panic_hook, trace_timestamp};
 
-use core::fmt::{self, Write};
+use core::{
+    fmt::{self, Write},
+    ptr,
+    sync::atomic::{
+        AtomicPtr, AtomicUsize, Ordering::Acquire, Ordering::Relaxed, Ordering::Release,
+    },
+};
 
+// The record is only ever read by Rust handlers. C handlers should treat it as an opaque pointer
+#[allow(improper_ctypes)]
 unsafe extern "C" {
-    fn _on_trace(level: Level, msg: *const u8, msg_len: usize);
+    // Handlers that don't take a record define `_on_trace` instead. The weak default of this
+    // symbol forwards to it
+    fn _on_trace_record(level: Level, msg: *const u8, msg_len: usize, record: *const Record);
 }
 
+/// Severity of a trace. The values are passed to C handlers and in interned frames, so they are
+/// kept stable, and levels added later don't follow the order of severity
 #[repr(C)]
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Level {
-    Debug,
-    Info,
-    Warning,
-    Error,
+    Trace = 4,
+    Debug = 0,
+    Info = 1,
+    Warning = 2,
+    Error = 3,
+    Fatal = 5,
 }
 
-pub(crate) const TRACE_FORMAT_BUFFER_SIZE: usize = 1024;
+impl Level {
+    /// All levels, from least to most severe
+    pub const ALL: [Level; 6] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warning,
+        Level::Error,
+        Level::Fatal,
+    ];
 
-/// A `TraceString` is a string that can be formatted up to a size of 1024. Anything larger than
-/// that will be cut of
+    /// Position of the level in [`Level::ALL`], from 0 for the least severe level
+    pub const fn severity(self) -> u8 {
+        match self {
+            Level::Trace => 0,
+            Level::Debug => 1,
+            Level::Info => 2,
+            Level::Warning => 3,
+            Level::Error => 4,
+            Level::Fatal => 5,
+        }
+    }
+}
+
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.severity().cmp(&other.severity())
+    }
+}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl TryFrom<u8> for Level {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0 => Ok(Level::Debug),
+            1 => Ok(Level::Info),
+            2 => Ok(Level::Warning),
+            3 => Ok(Level::Error),
+            4 => Ok(Level::Trace),
+            5 => Ok(Level::Fatal),
+            _ => Err(value),
+        }
+    }
+}
+
+// Defines `TRACE_FORMAT_BUFFER_SIZE`, which defaults to 1024 and is set by build.rs
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
+
+const DEFAULT_TRUNCATION_MARKER: &str = "…[truncated]";
+
+// Stored behind another reference, as a `&str` is two words and can't be swapped atomically
+static TRUNCATION_MARKER: AtomicPtr<&'static str> = AtomicPtr::new(ptr::null_mut());
+static TRUNCATED_MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the marker that ends messages that were too long to be formatted. Defaults to
+/// `…[truncated]`, and can be set to an empty string to cut messages without a marker
+///
+/// ```ignore
+/// static MARKER: &str = "~";
+/// trace::set_truncation_marker(&MARKER);
+/// ```
+pub fn set_truncation_marker(marker: &'static &'static str) {
+    TRUNCATION_MARKER.store(ptr::from_ref(marker).cast_mut(), Release);
+}
+
+pub fn truncation_marker() -> &'static str {
+    // Only ever set from `&'static &'static str`
+    unsafe { TRUNCATION_MARKER.load(Acquire).as_ref() }
+        .copied()
+        .unwrap_or(DEFAULT_TRUNCATION_MARKER)
+}
+
+/// Number of messages that have been truncated since startup
+pub fn truncated_messages() -> usize {
+    TRUNCATED_MESSAGES.load(Relaxed)
+}
+
+/// Largest index up to `index` that doesn't split a character
+fn floor_char_boundary(string: &str, index: usize) -> usize {
+    if index >= string.len() {
+        return string.len();
+    }
+    (0..=index)
+        .rev()
+        .find(|&i| string.is_char_boundary(i))
+        .unwrap_or(0)
+}
+
+/// A `TraceString` is a string that can be formatted up to a size of `TRACE_FORMAT_BUFFER_SIZE`.
+/// Anything larger than that is cut at a character boundary and ends with the truncation marker
 pub(crate) struct TraceString {
     pub(crate) length: usize,
     pub(crate) buffer: [u8; TRACE_FORMAT_BUFFER_SIZE],
+    truncated: bool,
 }
 
 impl TraceString {
@@ -40,27 +198,126 @@ impl TraceString {
         Self {
             length: 0,
             buffer: [0; TRACE_FORMAT_BUFFER_SIZE],
+            truncated: false,
         }
     }
 
     fn to_string(&self) -> &str {
         core::str::from_utf8(&self.buffer[..self.length]).unwrap()
     }
+
+    #[cfg_attr(
+        any(not(feature = "static-buffer"), feature = "interned"),
+        allow(dead_code)
+    )]
+    fn clear(&mut self) {
+        self.length = 0;
+        self.truncated = false;
+    }
 }
 
 impl Write for TraceString {
     fn write_str(&mut self, string: &str) -> core::fmt::Result {
-        // Just cut this part of the string of
-        if self.length == TRACE_FORMAT_BUFFER_SIZE {
-            return Ok(());
+        // Anything after the marker is dropped
+        if !self.truncated {
+            self.truncated = !append_or_truncate(&mut self.buffer, &mut self.length, string);
         }
+        Ok(())
+    }
+}
 
-        let max_character_length = usize::min(string.len(), TRACE_FORMAT_BUFFER_SIZE - self.length);
-        let source_substring = &string[0..max_character_length];
-        let dest_charlist = &mut self.buffer[self.length..self.length + max_character_length];
+/// Append `string` to the text in `buffer[..*length]`. If it doesn't fit, the text is cut at a
+/// character boundary and ends with the truncation marker, and false is returned
+fn append_or_truncate(buffer: &mut [u8], length: &mut usize, string: &str) -> bool {
+    fn push(buffer: &mut [u8], length: &mut usize, string: &str) {
+        buffer[*length..*length + string.len()].copy_from_slice(string.as_bytes());
+        *length += string.len();
+    }
 
-        dest_charlist.copy_from_slice(source_substring.as_bytes());
-        self.length += max_character_length;
+    let size = buffer.len();
+    if string.len() <= size - *length {
+        push(buffer, length, string);
+        return true;
+    }
+
+    TRUNCATED_MESSAGES.fetch_add(1, Relaxed);
+
+    let marker = truncation_marker();
+    let marker = &marker[..floor_char_boundary(marker, size)];
+    let end = size - marker.len();
+    if *length > end {
+        // Make room for the marker in what has already been written, without splitting the
+        // character that starts at the cut
+        while *length > end || buffer[*length] & 0xc0 == 0x80 {
+            *length -= 1;
+        }
+    } else {
+        let cut = floor_char_boundary(string, end - *length);
+        push(buffer, length, &string[..cut]);
+    }
+    push(buffer, length, marker);
+    false
+}
+
+/// A string formatted into a buffer on the stack, for messages that can't use the format buffer as it
+/// may be in use. Anything that doesn't fit is cut at a character boundary, without a marker
+pub(crate) struct StackString<const N: usize> {
+    length: usize,
+    buffer: [u8; N],
+}
+
+impl<const N: usize> StackString<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            length: 0,
+            buffer: [0; N],
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        let bytes = &self.buffer[..self.length];
+        core::str::from_utf8(bytes).unwrap_or_else(|error| {
+            core::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap_or_default()
+        })
+    }
+
+    /// Pass the string on to `f` as a record, in a frame without a format string index in interned
+    /// mode
+    pub(crate) fn with_record<R>(
+        &self,
+        level: Level,
+        timestamp: Timestamp,
+        callsite: &Callsite,
+        f: impl FnOnce(&Record) -> R,
+    ) -> R {
+        #[cfg(not(feature = "interned"))]
+        return f(&Record::new(level, timestamp, callsite, self.as_str(), &[]));
+
+        #[cfg(feature = "interned")]
+        {
+            let mut frame = interned::Frame::new(level, interned::RAW_INDEX);
+            frame.push_str(self.as_str());
+            f(&Record::new(
+                level,
+                timestamp,
+                callsite,
+                frame.as_bytes(),
+                &[],
+            ))
+        }
+    }
+}
+
+impl<const N: usize> Write for StackString<N> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let length = floor_char_boundary(string, N - self.length);
+        self.buffer[self.length..self.length + length]
+            .copy_from_slice(&string.as_bytes()[..length]);
+        self.length += length;
+        // Stop at the first cut, so that nothing after it is added
+        if length < string.len() {
+            return Err(fmt::Error);
+        }
         Ok(())
     }
 }
@@ -71,11 +328,13 @@ impl Clone for TraceString {
     fn clone(&self) -> Self {
         Self {
             length: self.length,
-            buffer: self.buffer.clone(),
+            buffer: self.buffer,
+            truncated: self.truncated,
         }
     }
 }
 
+#[cfg(any(test, not(any(feature = "static-buffer", feature = "interned"))))]
 pub(crate) fn format(args: fmt::Arguments) -> TraceString {
     let mut res = TraceString::new();
     unsafe {
@@ -84,31 +343,410 @@ pub(crate) fn format(args: fmt::Arguments) -> TraceString {
     res
 }
 
+/// Format a message on the stack and pass it to `f`
+#[cfg(not(any(feature = "static-buffer", feature = "interned")))]
+pub(crate) fn with_formatted<R>(args: fmt::Arguments, f: impl FnOnce(&str) -> R) -> Option<R> {
+    Some(f(format(args).to_string()))
+}
+
+/// Static format buffer of one core for the `static-buffer` feature. Like the ring buffer, it is
+/// never waited on, so it doesn't need a critical section
+#[cfg(all(feature = "static-buffer", not(feature = "interned")))]
+struct FormatBuffer {
+    locked: core::sync::atomic::AtomicBool,
+    buffer: core::cell::UnsafeCell<TraceString>,
+}
+
+// The buffer is only accessed while holding the lock
+#[cfg(all(feature = "static-buffer", not(feature = "interned")))]
+unsafe impl Sync for FormatBuffer {}
+
+#[cfg(all(feature = "static-buffer", not(feature = "interned")))]
+static FORMAT_BUFFERS: [FormatBuffer; MAX_CORES] = [const {
+    FormatBuffer {
+        locked: core::sync::atomic::AtomicBool::new(false),
+        buffer: core::cell::UnsafeCell::new(TraceString::new()),
+    }
+}; MAX_CORES];
+
+/// Check if the static buffer of the current core is being formatted into or read from
+#[cfg(all(
+    feature = "static-buffer",
+    not(feature = "interned"),
+    any(feature = "panic-handler", test)
+))]
+pub(crate) fn format_buffer_in_use() -> bool {
+    FORMAT_BUFFERS[core_id()].locked.load(Relaxed)
+}
+
+/// Format a message in the static buffer of the current core and pass it to `f`. The buffer stays
+/// locked until `f` returns, but nothing is waited on. Returns `None` if the buffer is already in
+/// use, such as when tracing from a trace handler, in which case the trace is counted as a dropped
+/// nested trace
+#[cfg(all(feature = "static-buffer", not(feature = "interned")))]
+pub(crate) fn with_formatted<R>(args: fmt::Arguments, f: impl FnOnce(&str) -> R) -> Option<R> {
+    let slot = &FORMAT_BUFFERS[core_id()];
+    if slot
+        .locked
+        .compare_exchange(false, true, Acquire, Relaxed)
+        .is_err()
+    {
+        reentrancy::drop_nested();
+        return None;
+    }
+
+    let buffer = unsafe { &mut *slot.buffer.get() };
+    buffer.clear();
+    let _ = fmt::write(buffer, args);
+    let res = f(buffer.to_string());
+    slot.locked.store(false, Release);
+    Some(res)
+}
+
+/// Format a message and pass it on to the trace handler. This is not affected by the runtime level
+/// filter, but is dropped if the handler doesn't accept the level. The callsite of the record is
+/// the location of the caller, without a target or module path
+#[track_caller]
 pub fn trace_format(level: Level, args: fmt::Arguments) {
-    let formatted = format(args);
-    let string = formatted.to_string();
-    unsafe { _on_trace(level, string.as_ptr(), string.len()) };
+    if !handler_enabled(level) {
+        return;
+    }
+
+    let location = core::panic::Location::caller();
+    let callsite = Callsite::new("", "", location.file(), location.line());
+    trace_format_at(&callsite, level, Timestamp::now(), &[], args);
+}
+
+/// Format a message and pass it on to the trace handler together with its callsite, timestamp and
+/// fields. Does not check any filters, which is up to the caller
+#[cfg(not(feature = "interned"))]
+pub fn trace_format_at(
+    callsite: &Callsite,
+    level: Level,
+    timestamp: Timestamp,
+    fields: &[Field],
+    args: fmt::Arguments,
+) {
+    // Deferred records are passed on later, so they can't be streamed while they are formatted
+    let handler = stream::stream_handler().filter(|_| !cfg!(feature = "deferred"));
+    let Some(handler) = handler else {
+        with_formatted(args, |message| {
+            dispatch(&Record::new(level, timestamp, callsite, message, fields))
+        });
+        return;
+    };
+
+    if !reentrancy::enter() {
+        with_formatted(args, |message| {
+            reentrancy::nested(&Record::new(level, timestamp, callsite, message, fields))
+        });
+        return;
+    }
+
+    // The stream handler replaces the trace handler, but sinks still need the formatted message
+    if handler.enabled(level) {
+        stream::stream(handler, callsite, level, fields, args);
+    }
+    if sink::sinks_enabled(level) {
+        with_formatted(args, |message| {
+            sink::dispatch_sinks(&Record::new(level, timestamp, callsite, message, fields))
+        });
+    }
+    reentrancy::exit();
+}
+
+/// In interned mode, formatted text is sent as a frame without a format string index, so that the
+/// handler only has to deal with one kind of payload. The text is formatted straight into the frame
+/// on the stack, like the frames of the trace macros, so the `static-buffer` feature has no effect
+#[cfg(feature = "interned")]
+pub fn trace_format_at(
+    callsite: &Callsite,
+    level: Level,
+    timestamp: Timestamp,
+    fields: &[Field],
+    args: fmt::Arguments,
+) {
+    let mut frame = interned::Frame::new(level, interned::RAW_INDEX);
+    frame.push_formatted(args);
+    frame.emit_at(timestamp, callsite, fields);
+}
+
+/// Shared implementation of the trace macros. Lays out the message with the registered style for
+/// `styled` macros, and wraps it in the prefix and suffix for `plain` ones
+#[doc(hidden)]
+#[cfg(not(feature = "interned"))]
+#[macro_export]
+macro_rules! __trace_write {
+    ($style:ident, $level:expr, $prefix:literal, $suffix:literal, target: $target:expr, $($key:ident = $value:expr),+ ; $($arg:tt)*) => {{
+        let callsite = $crate::__trace_callsite!($target);
+        if $crate::target_enabled($level, callsite.target()) && $crate::handler_enabled($level) {
+            let timestamp = $crate::Timestamp::now();
+            $crate::trace_format_at(
+                callsite,
+                $level,
+                timestamp,
+                &[$($crate::Field::new(stringify!($key), $crate::Value::from($value))),+],
+                $crate::__trace_message!($style, $level, timestamp, callsite, $prefix, $suffix, $($arg)*),
+            );
+        }
+    }};
+    ($style:ident, $level:expr, $prefix:literal, $suffix:literal, target: $target:expr, $($arg:tt)*) => {{
+        let callsite = $crate::__trace_callsite!($target);
+        if $crate::target_enabled($level, callsite.target()) && $crate::handler_enabled($level) {
+            let timestamp = $crate::Timestamp::now();
+            $crate::trace_format_at(
+                callsite,
+                $level,
+                timestamp,
+                &[],
+                $crate::__trace_message!($style, $level, timestamp, callsite, $prefix, $suffix, $($arg)*),
+            );
+        }
+    }};
+    ($style:ident, $level:expr, $prefix:literal, $suffix:literal, $($arg:tt)*) => {
+        $crate::__trace_write!($style, $level, $prefix, $suffix, target: module_path!(), $($arg)*)
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "interned"))]
+#[macro_export]
+macro_rules! __trace_message {
+    (styled, $level:expr, $timestamp:expr, $callsite:expr, $prefix:literal, $suffix:literal, $($arg:tt)*) => {
+        format_args!("{}", $crate::Styled::new($level, $timestamp, $callsite, format_args!($($arg)*)))
+    };
+    (plain, $level:expr, $timestamp:expr, $callsite:expr, $prefix:literal, $suffix:literal, $($arg:tt)*) => {
+        format_args!(concat!($prefix, "{}", $suffix), format_args!($($arg)*))
+    };
+}
+
+/// Shared implementation of the trace macros. Interns the prefix, format string and suffix, and
+/// only encodes the arguments at runtime. The style is part of the interned string, so the
+/// registered style is not used. The format string is checked against the arguments by
+/// `__trace_encode!`, as it is never passed to `format_args!`
+#[doc(hidden)]
+#[cfg(feature = "interned")]
+#[macro_export]
+macro_rules! __trace_write {
+    ($style:ident, $level:expr, $prefix:literal, $suffix:literal, target: $target:expr, $($key:ident = $value:expr),+ ; $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        let callsite = $crate::__trace_callsite!($target);
+        if $crate::target_enabled($level, callsite.target()) && $crate::handler_enabled($level) {
+            // Only pushed to if there are arguments
+            #[allow(unused_mut)]
+            let mut frame = $crate::interned::Frame::new(
+                $level,
+                $crate::__trace_intern!(concat!($prefix, $fmt, $suffix)),
+            );
+            $crate::__trace_encode!(frame, $fmt $(, $arg)*);
+            frame.emit(
+                callsite,
+                &[$($crate::Field::new(stringify!($key), $crate::Value::from($value))),+],
+            );
+        }
+    }};
+    ($style:ident, $level:expr, $prefix:literal, $suffix:literal, target: $target:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        let callsite = $crate::__trace_callsite!($target);
+        if $crate::target_enabled($level, callsite.target()) && $crate::handler_enabled($level) {
+            // Only pushed to if there are arguments
+            #[allow(unused_mut)]
+            let mut frame = $crate::interned::Frame::new(
+                $level,
+                $crate::__trace_intern!(concat!($prefix, $fmt, $suffix)),
+            );
+            $crate::__trace_encode!(frame, $fmt $(, $arg)*);
+            frame.emit(callsite, &[]);
+        }
+    }};
+    ($style:ident, $level:expr, $prefix:literal, $suffix:literal, $($arg:tt)*) => {
+        $crate::__trace_write!($style, $level, $prefix, $suffix, target: module_path!(), $($arg)*)
+    };
+}
+
+/// Message of a trace handler function taking `msg: &str`, as generated by `#[trace_handler]`.
+/// Messages are formatted into valid UTF-8, but are cut at the first invalid sequence rather than
+/// panicking, as a panic can't unwind out of the handler entry point
+#[doc(hidden)]
+#[cfg(not(feature = "interned"))]
+#[macro_export]
+macro_rules! __trace_handler_str {
+    ($bytes:expr) => {
+        match core::str::from_utf8($bytes) {
+            Ok(message) => message,
+            Err(error) => unsafe { core::str::from_utf8_unchecked(&$bytes[..error.valid_up_to()]) },
+        }
+    };
+}
+
+/// Frames of the `interned` feature are not text, so handlers have to take them as bytes
+#[doc(hidden)]
+#[cfg(feature = "interned")]
+#[macro_export]
+macro_rules! __trace_handler_str {
+    ($bytes:expr) => {
+        compile_error!(
+            "trace handlers take the frame as `frame: &[u8]` instead of `msg: &str` with the `interned` feature"
+        )
+    };
+}
+
+// Level gates used by the trace macros. A gate expands to its body if the level is traced in the
+// profile the trace crate is built with, and to nothing otherwise. The profile is checked here
+// rather than in the caller, so that `always-on` and `always-off` apply to every caller. The cfgs
+// are set by the build script
+
+#[doc(hidden)]
+#[cfg(any(trace_release_level_trace, all(debug_assertions, trace_level_trace)))]
+#[macro_export]
+macro_rules! __trace_if_trace {
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(any(trace_release_level_trace, all(debug_assertions, trace_level_trace))))]
+#[macro_export]
+macro_rules! __trace_if_trace {
+    ($($body:tt)*) => {{}};
+}
+
+#[doc(hidden)]
+#[cfg(any(trace_release_level_debug, all(debug_assertions, trace_level_debug)))]
+#[macro_export]
+macro_rules! __trace_if_debug {
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(any(trace_release_level_debug, all(debug_assertions, trace_level_debug))))]
+#[macro_export]
+macro_rules! __trace_if_debug {
+    ($($body:tt)*) => {{}};
+}
+
+#[doc(hidden)]
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[macro_export]
+macro_rules! __trace_if_info {
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(any(trace_release_level_info, all(debug_assertions, trace_level_info))))]
+#[macro_export]
+macro_rules! __trace_if_info {
+    ($($body:tt)*) => {{}};
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+#[doc(hidden)]
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
+#[macro_export]
+macro_rules! __trace_if_warning {
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+)))]
+#[macro_export]
+macro_rules! __trace_if_warning {
+    ($($body:tt)*) => {{}};
+}
+
+#[doc(hidden)]
+#[cfg(any(trace_release_level_error, all(debug_assertions, trace_level_error)))]
+#[macro_export]
+macro_rules! __trace_if_error {
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(any(trace_release_level_error, all(debug_assertions, trace_level_error))))]
+#[macro_export]
+macro_rules! __trace_if_error {
+    ($($body:tt)*) => {{}};
+}
+
+#[doc(hidden)]
+#[cfg(any(trace_release_level_fatal, all(debug_assertions, trace_level_fatal)))]
+#[macro_export]
+macro_rules! __trace_if_fatal {
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
+}
+
+#[doc(hidden)]
+#[cfg(not(any(trace_release_level_fatal, all(debug_assertions, trace_level_fatal))))]
+#[macro_export]
+macro_rules! __trace_if_fatal {
+    ($($body:tt)*) => {{}};
+}
+
+/// Check if the macros of a level are compiled in, for macros that take the level as an argument.
+/// Uses the same cfgs as the level gates above
+#[doc(hidden)]
+pub const fn __trace_level_compiled_in(level: Level) -> bool {
+    match level {
+        Level::Trace => cfg!(any(
+            trace_release_level_trace,
+            all(debug_assertions, trace_level_trace)
+        )),
+        Level::Debug => cfg!(any(
+            trace_release_level_debug,
+            all(debug_assertions, trace_level_debug)
+        )),
+        Level::Info => cfg!(any(
+            trace_release_level_info,
+            all(debug_assertions, trace_level_info)
+        )),
+        Level::Warning => cfg!(any(
+            trace_release_level_warning,
+            all(debug_assertions, trace_level_warning)
+        )),
+        Level::Error => cfg!(any(
+            trace_release_level_error,
+            all(debug_assertions, trace_level_error)
+        )),
+        Level::Fatal => cfg!(any(
+            trace_release_level_fatal,
+            all(debug_assertions, trace_level_fatal)
+        )),
+    }
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! trace {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Info, format_args!($($arg)*));
+        $crate::__trace_if_info! {
+            $crate::__trace_write!(plain, $crate::Level::Info, "", "", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! trace_once {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
+        $crate::__trace_if_info! {
             use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
             static HAS_RUN: AtomicBool = AtomicBool::new(false);
             if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
@@ -118,39 +756,39 @@ macro_rules! trace_once {
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(not(feature = "no-color"))]
 #[macro_export]
 macro_rules! traceln {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Info, format_args!("\x1b[0m{}\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_info! {
+            $crate::__trace_write!(plain, $crate::Level::Info, "\x1b[0m", "\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(feature = "no-color")]
 #[macro_export]
 macro_rules! traceln {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Info, format_args!("{}\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_info! {
+            $crate::__trace_write!(plain, $crate::Level::Info, "", "\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! traceln_once {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
+        $crate::__trace_if_info! {
             use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
             static HAS_RUN: AtomicBool = AtomicBool::new(false);
             if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
@@ -160,39 +798,81 @@ macro_rules! traceln_once {
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
+#[cfg(not(feature = "no-color"))]
+#[macro_export]
+macro_rules! trace_trace {
+    ($($arg:tt)*) => {
+        $crate::__trace_if_trace! {
+            $crate::__trace_write!(styled, $crate::Level::Trace, "\x1b[34mTRACE: ", "\x1b[0m\r\n", $($arg)*);
+        }
+    };
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
+#[cfg(feature = "no-color")]
+#[macro_export]
+macro_rules! trace_trace {
+    ($($arg:tt)*) => {
+        $crate::__trace_if_trace! {
+            $crate::__trace_write!(styled, $crate::Level::Trace, "TRACE: ", "\r\n", $($arg)*);
+        }
+    };
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
+#[macro_export]
+macro_rules! trace_trace_once {
+    ($($arg:tt)*) => {
+        $crate::__trace_if_trace! {
+            use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
+            static HAS_RUN: AtomicBool = AtomicBool::new(false);
+            if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
+                $crate::trace_trace!($($arg)*);
+            }
+        }
+    };
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(not(feature = "no-color"))]
 #[macro_export]
 macro_rules! trace_debug {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Debug, format_args!("\x1b[35mDEBUG: {}\x1b[0m\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_debug! {
+            $crate::__trace_write!(styled, $crate::Level::Debug, "\x1b[35mDEBUG: ", "\x1b[0m\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(feature = "no-color")]
 #[macro_export]
 macro_rules! trace_debug {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Debug, format_args!("DEBUG: {}\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_debug! {
+            $crate::__trace_write!(styled, $crate::Level::Debug, "DEBUG: ", "\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! trace_debug_once {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
+        $crate::__trace_if_debug! {
             use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
             static HAS_RUN: AtomicBool = AtomicBool::new(false);
             if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
@@ -202,39 +882,39 @@ macro_rules! trace_debug_once {
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(not(feature = "no-color"))]
 #[macro_export]
 macro_rules! trace_info {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Info, format_args!("\x1b[32mINFO: {}\x1b[0m\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_info! {
+            $crate::__trace_write!(styled, $crate::Level::Info, "\x1b[32mINFO: ", "\x1b[0m\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(feature = "no-color")]
 #[macro_export]
 macro_rules! trace_info {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Info, format_args!("INFO: {}\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_info! {
+            $crate::__trace_write!(styled, $crate::Level::Info, "INFO: ", "\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! trace_info_once {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
+        $crate::__trace_if_info! {
             use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
             static HAS_RUN: AtomicBool = AtomicBool::new(false);
             if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
@@ -244,39 +924,39 @@ macro_rules! trace_info_once {
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(not(feature = "no-color"))]
 #[macro_export]
 macro_rules! trace_warning {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Warning, format_args!("\x1b[33mWARNING: {}\x1b[0m\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_warning! {
+            $crate::__trace_write!(styled, $crate::Level::Warning, "\x1b[33mWARNING: ", "\x1b[0m\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(feature = "no-color")]
 #[macro_export]
 macro_rules! trace_warning {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Warning, format_args!("WARNING: {}\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_warning! {
+            $crate::__trace_write!(styled, $crate::Level::Warning, "WARNING: ", "\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! trace_warning_once {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
+        $crate::__trace_if_warning! {
             use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
             static HAS_RUN: AtomicBool = AtomicBool::new(false);
             if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
@@ -286,39 +966,39 @@ macro_rules! trace_warning_once {
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 #[cfg(not(feature = "no-color"))]
 macro_rules! trace_error {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Error, format_args!("\x1b[31mERROR: {}\x1b[0m\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_error! {
+            $crate::__trace_write!(styled, $crate::Level::Error, "\x1b[31mERROR: ", "\x1b[0m\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 #[cfg(feature = "no-color")]
 macro_rules! trace_error {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Error, format_args!("ERROR: {}\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_error! {
+            $crate::__trace_write!(styled, $crate::Level::Error, "ERROR: ", "\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[macro_export]
 macro_rules! trace_error_once {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
+        $crate::__trace_if_error! {
             use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
             static HAS_RUN: AtomicBool = AtomicBool::new(false);
             if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
@@ -328,28 +1008,232 @@ macro_rules! trace_error_once {
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
+#[cfg(not(feature = "no-color"))]
+#[macro_export]
+macro_rules! trace_fatal {
+    ($($arg:tt)*) => {
+        $crate::__trace_if_fatal! {
+            $crate::__trace_write!(styled, $crate::Level::Fatal, "\x1b[31mFATAL: ", "\x1b[0m\r\n", $($arg)*);
+        }
+    };
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
+#[cfg(feature = "no-color")]
+#[macro_export]
+macro_rules! trace_fatal {
+    ($($arg:tt)*) => {
+        $crate::__trace_if_fatal! {
+            $crate::__trace_write!(styled, $crate::Level::Fatal, "FATAL: ", "\r\n", $($arg)*);
+        }
+    };
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
+#[macro_export]
+macro_rules! trace_fatal_once {
+    ($($arg:tt)*) => {
+        $crate::__trace_if_fatal! {
+            use core::sync::atomic::{AtomicBool, Ordering::SeqCst, Ordering::Relaxed};
+            static HAS_RUN: AtomicBool = AtomicBool::new(false);
+            if HAS_RUN.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
+                $crate::trace_fatal!($($arg)*);
+            }
+        }
+    };
+}
+
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(not(feature = "no-color"))]
 #[macro_export]
 macro_rules! trace_panic {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        {
-            $crate::trace_format($crate::Level::Error, format_args!("\x1b[31mPANIC: {}\x1b[0m\r\n", format_args!($($arg)*)));
+        $crate::__trace_if_fatal! {
+            $crate::__trace_write!(plain, $crate::Level::Fatal, "\x1b[31mPANIC: ", "\x1b[0m\r\n", $($arg)*);
         }
     };
 }
 
-/// Tracing macro for simplifying the usage of the trace functionality. Will panic if the formatted
-/// string is to long
+/// Tracing macro for simplifying the usage of the trace functionality. Messages longer than the
+/// format buffer are cut and end with the truncation marker, and are counted by
+/// [`truncated_messages`]
 #[cfg(feature = "no-color")]
 #[macro_export]
 macro_rules! trace_panic {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
+        $crate::__trace_if_fatal! {
+            $crate::__trace_write!(plain, $crate::Level::Fatal, "PANIC: ", "\r\n", $($arg)*);
+        }
+    };
+}
+
+/// Run a trace macro on every `n`th call, starting with the first one. Used to report from a tight
+/// loop without flooding the trace handler
+///
+/// ```ignore
+/// trace_every_n!(100, trace_warning!("Overcurrent: {} mA", current));
+/// ```
+#[macro_export]
+macro_rules! trace_every_n {
+    ($n:expr, $($trace:tt)*) => {{
+        use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        if COUNT.fetch_add(1, Relaxed) % usize::max($n, 1) == 0 {
+            $($trace)*;
+        }
+    }};
+}
+
+/// Run a trace macro on the first `n` calls only
+///
+/// ```ignore
+/// trace_first_n!(3, trace_error!("CRC mismatch in frame {}", id));
+/// ```
+#[macro_export]
+macro_rules! trace_first_n {
+    ($n:expr, $($trace:tt)*) => {{
+        use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        // Checked before counting, so that the counter can't overflow
+        if COUNT.load(Relaxed) < $n && COUNT.fetch_add(1, Relaxed) < $n {
+            $($trace)*;
+        }
+    }};
+}
+
+/// Run a trace macro at most once per `ticks` ticks. The time is read from the source registered
+/// with `#[trace_timestamp]`, or can be passed explicitly with `now:`
+///
+/// Without a `#[trace_timestamp]` source the time stays at 0, so the form without `now:` runs the
+/// trace macro once and never again. Pass `now:` unless a timestamp source is registered
+///
+/// ```ignore
+/// trace_throttle!(1_000_000, trace_warning!("Motor stalled"));
+/// trace_throttle!(1000, now: systick(), trace_warning!("Motor stalled"));
+/// ```
+#[macro_export]
+macro_rules! trace_throttle {
+    ($ticks:expr, now: $now:expr, $($trace:tt)*) => {{
+        static THROTTLE: $crate::Throttle = $crate::Throttle::new();
+        if THROTTLE.ready($now, $ticks) {
+            $($trace)*;
+        }
+    }};
+    ($ticks:expr, $($trace:tt)*) => {
+        $crate::trace_throttle!($ticks, now: $crate::Timestamp::now().ticks(), $($trace)*)
+    };
+}
+
+/// Trace a byte buffer as rows of offset, hex and ASCII, after a header with the label and length.
+/// Every row is passed to the handler as a separate record, so buffers of any length are traced in
+/// full. Like the other macros, levels that are not compiled in never evaluate the label or bytes
+///
+/// ```ignore
+/// trace_hexdump!(Level::Debug, "CAN frame", &frame.data);
+/// ```
+#[macro_export]
+macro_rules! trace_hexdump {
+    ($level:expr, $label:expr, $bytes:expr $(,)?) => {{
+        let callsite = $crate::__trace_callsite!(module_path!());
+        let level = $level;
+        if $crate::__trace_level_compiled_in(level)
+            && $crate::target_enabled(level, callsite.target())
+            && $crate::handler_enabled(level)
         {
-            $crate::trace_format($crate::Level::Error, format_args!("PANIC: {}\r\n", format_args!($($arg)*)));
+            $crate::trace_hexdump_at(callsite, level, $label, $bytes);
+        }
+    }};
+}
+
+/// Assert that a condition holds. On failure, the condition and location are traced at the error
+/// level before acting on the [`AssertAction`], which panics by default
+///
+/// ```ignore
+/// trace_assert!(len <= BUFFER_SIZE);
+/// trace_assert!(crc == expected, "CRC mismatch in frame {}", id);
+/// ```
+#[macro_export]
+macro_rules! trace_assert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::assert_failed(
+                $crate::__trace_callsite!(module_path!()),
+                format_args!("assertion failed: {}", stringify!($cond)),
+            );
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::assert_failed(
+                $crate::__trace_callsite!(module_path!()),
+                format_args!("assertion failed: {}: {}", stringify!($cond), format_args!($($arg)+)),
+            );
+        }
+    };
+}
+
+/// Assert that two expressions are equal. On failure, both expressions and their values are traced
+/// at the error level, as done by [`trace_assert!`]
+///
+/// ```ignore
+/// trace_assert_eq!(header.version, 2);
+/// ```
+#[macro_export]
+macro_rules! trace_assert_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::assert_failed(
+                        $crate::__trace_callsite!(module_path!()),
+                        format_args!(
+                            "assertion failed: {} == {} (left: {:?}, right: {:?})",
+                            stringify!($left),
+                            stringify!($right),
+                            left,
+                            right
+                        ),
+                    );
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::assert_failed(
+                        $crate::__trace_callsite!(module_path!()),
+                        format_args!(
+                            "assertion failed: {} == {} (left: {:?}, right: {:?}): {}",
+                            stringify!($left),
+                            stringify!($right),
+                            left,
+                            right,
+                            format_args!($($arg)+)
+                        ),
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// [`trace_assert!`] that is only checked in builds with debug assertions, like `debug_assert!`
+#[macro_export]
+macro_rules! trace_debug_assert {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::trace_assert!($($arg)*);
         }
     };
 }
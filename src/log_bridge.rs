@@ -0,0 +1,80 @@
+//! Bridge from the `log` crate facade, provided by the `log` feature. Records logged with
+//! `log::info!` and friends are formatted like the trace macro of the same level, and passed on to
+//! the trace handler
+
+use crate::{Callsite, Level, Styled, Timestamp, handler_enabled, target_enabled, trace_format_at};
+
+/// Logger passing `log` records on to the trace handler. Register it with [`init`], or with
+/// `log::set_logger` directly
+pub struct Logger;
+
+/// The logger registered by [`init`]
+pub static LOGGER: Logger = Logger;
+
+/// Register [`LOGGER`] as the `log` logger. Levels are filtered by the runtime filter of this
+/// crate, so the `log` max level is set to let everything through
+pub fn init() -> Result<(), log::SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(log::LevelFilter::Trace);
+    Ok(())
+}
+
+impl From<log::Level> for Level {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warning,
+            log::Level::Info => Level::Info,
+            log::Level::Debug => Level::Debug,
+            log::Level::Trace => Level::Trace,
+        }
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        // `log` macros are not removed by the level gates, so they are checked here
+        let level = Level::from(metadata.level());
+        crate::__trace_level_compiled_in(level)
+            && target_enabled(level, metadata.target())
+            && handler_enabled(level)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Callsites only hold static strings. The target is usually the module path, but can't be
+        // passed on when it's set to something else
+        let module_path = record.module_path_static().unwrap_or("");
+        let target = if record.target() == module_path {
+            module_path
+        } else {
+            ""
+        };
+        let callsite = Callsite::new(
+            target,
+            module_path,
+            record.file_static().unwrap_or(""),
+            record.line().unwrap_or(0),
+        );
+
+        let level = Level::from(record.level());
+        let timestamp = Timestamp::now();
+        trace_format_at(
+            &callsite,
+            level,
+            timestamp,
+            &[],
+            format_args!(
+                "{}",
+                Styled::new(level, timestamp, &callsite, *record.args())
+            ),
+        );
+    }
+
+    fn flush(&self) {
+        crate::flush();
+    }
+}
@@ -0,0 +1,112 @@
+//! Panic handler provided by the `panic-handler` feature. Traces the panic location and message at
+//! the fatal level, passes on deferred records, flushes the trace handler and then calls the panic
+//! hook registered with `#[trace_panic_hook]`. Without a panic hook, it loops forever
+//!
+//! A panic in the trace handler, or while the format buffer is in use, can't go through the
+//! handlers again. The location and message are then formatted on the stack and passed straight to
+//! the handler registered with `#[trace_handler]`, before flushing it
+
+use core::{
+    fmt::{self, Write},
+    panic::Location,
+};
+
+use crate::{Callsite, Level, Record, StackString, Timestamp, flush, reentrancy, trace_format};
+
+// Defined by `#[trace_panic_hook]`. The weak default loops forever
+#[cfg(all(feature = "panic-handler", not(test)))]
+unsafe extern "C" {
+    #[allow(improper_ctypes)]
+    fn _on_trace_panic(info: &core::panic::PanicInfo) -> !;
+}
+
+// Defined by `#[trace_handler]`. Used when the handlers can't be entered
+#[allow(improper_ctypes)]
+unsafe extern "C" {
+    fn _on_trace_record(level: Level, msg: *const u8, msg_len: usize, record: *const Record);
+    fn _on_trace_flush();
+}
+
+#[cfg(not(feature = "no-color"))]
+const PREFIX: &str = "\x1b[31mPANIC: ";
+#[cfg(not(feature = "no-color"))]
+const SUFFIX: &str = "\x1b[0m\r\n";
+#[cfg(feature = "no-color")]
+const PREFIX: &str = "PANIC: ";
+#[cfg(feature = "no-color")]
+const SUFFIX: &str = "\r\n";
+
+// Longer messages are cut when the handlers can't be entered
+const FALLBACK_MESSAGE_SIZE: usize = 128;
+
+static FALLBACK_CALLSITE: Callsite =
+    Callsite::new(module_path!(), module_path!(), file!(), line!());
+
+// Tests run with std, which has its own panic handler
+#[cfg(all(feature = "panic-handler", not(test)))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    handle(info.location(), info.message(), || unsafe {
+        _on_trace_panic(info)
+    })
+}
+
+/// Trace a panic, pass on everything that is still queued and then call `halt`, which is the panic
+/// hook outside of tests
+pub(crate) fn handle<R>(
+    location: Option<&Location>,
+    message: impl fmt::Display,
+    halt: impl FnOnce() -> R,
+) -> R {
+    let report = Report { location, message };
+    if busy() {
+        let timestamp = Timestamp::now();
+        let mut fallback = StackString::<FALLBACK_MESSAGE_SIZE>::new();
+        let _ = write!(fallback, "{}", report);
+        fallback.with_record(Level::Fatal, timestamp, &FALLBACK_CALLSITE, |record| {
+            let bytes = record.bytes();
+            unsafe {
+                _on_trace_record(record.level(), bytes.as_ptr(), bytes.len(), record);
+                _on_trace_flush();
+            }
+        });
+    } else {
+        trace_format(Level::Fatal, format_args!("{}", report));
+        // The queue is never drained otherwise once the main loop has stopped
+        #[cfg(feature = "deferred")]
+        crate::drain_to_handlers();
+        flush();
+    }
+    halt()
+}
+
+/// Check if the panic happened while the handlers or the format buffer are in use on this core
+fn busy() -> bool {
+    #[cfg(all(feature = "static-buffer", not(feature = "interned")))]
+    if crate::format_buffer_in_use() {
+        return true;
+    }
+    reentrancy::active()
+}
+
+/// Location and message of a panic, such as `PANIC: src/main.rs:42:5: index out of bounds`
+struct Report<'a, M> {
+    location: Option<&'a Location<'a>>,
+    message: M,
+}
+
+impl<M: fmt::Display> fmt::Display for Report<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(PREFIX)?;
+        if let Some(location) = self.location {
+            write!(
+                f,
+                "{}:{}:{}: ",
+                location.file(),
+                location.line(),
+                location.column()
+            )?;
+        }
+        write!(f, "{}{}", self.message, SUFFIX)
+    }
+}
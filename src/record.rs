@@ -0,0 +1,218 @@
+use core::fmt;
+
+use crate::{Callsite, Level, Timestamp};
+
+/// A single trace, as passed to the trace handler
+#[derive(Clone, Copy)]
+pub struct Record<'a> {
+    level: Level,
+    timestamp: Timestamp,
+    callsite: &'a Callsite,
+    #[cfg(not(feature = "interned"))]
+    message: &'a str,
+    #[cfg(feature = "interned")]
+    frame: &'a [u8],
+    fields: &'a [Field<'a>],
+}
+
+impl<'a> Record<'a> {
+    #[cfg(not(feature = "interned"))]
+    pub(crate) fn new(
+        level: Level,
+        timestamp: Timestamp,
+        callsite: &'a Callsite,
+        message: &'a str,
+        fields: &'a [Field<'a>],
+    ) -> Self {
+        Self {
+            level,
+            timestamp,
+            callsite,
+            message,
+            fields,
+        }
+    }
+
+    #[cfg(feature = "interned")]
+    pub(crate) fn new(
+        level: Level,
+        timestamp: Timestamp,
+        callsite: &'a Callsite,
+        frame: &'a [u8],
+        fields: &'a [Field<'a>],
+    ) -> Self {
+        Self {
+            level,
+            timestamp,
+            callsite,
+            frame,
+            fields,
+        }
+    }
+
+    /// Rebuild a record from the bytes of [`Record::store`], for records that are handled
+    /// after they were traced. Fields are not stored
+    pub(crate) fn stored(
+        level: Level,
+        timestamp: Timestamp,
+        callsite: &'a Callsite,
+        bytes: &'a [u8],
+    ) -> Self {
+        Self {
+            level,
+            timestamp,
+            callsite,
+            #[cfg(not(feature = "interned"))]
+            message: core::str::from_utf8(bytes).unwrap_or(""),
+            #[cfg(feature = "interned")]
+            frame: bytes,
+            fields: &[],
+        }
+    }
+
+    /// Check if the record can be stored in `size` bytes to be handled later. Messages can always
+    /// be cut, while frames that are too long can't be stored
+    pub(crate) fn can_store(&self, size: usize) -> bool {
+        cfg!(not(feature = "interned")) || self.bytes().len() <= size
+    }
+
+    /// Copy the bytes of a record that is handled later into `buffer`, and return their length.
+    /// Messages that don't fit are cut like formatted messages, at a character boundary and with
+    /// the truncation marker at the end
+    pub(crate) fn store(&self, buffer: &mut [u8]) -> usize {
+        #[cfg(not(feature = "interned"))]
+        {
+            let mut length = 0;
+            crate::append_or_truncate(buffer, &mut length, self.message);
+            length
+        }
+        #[cfg(feature = "interned")]
+        {
+            buffer[..self.frame.len()].copy_from_slice(self.frame);
+            self.frame.len()
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Time the record was created, read from the source registered with `#[trace_timestamp]`
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn callsite(&self) -> &'a Callsite {
+        self.callsite
+    }
+
+    /// The formatted message, including its prefix and suffix
+    #[cfg(not(feature = "interned"))]
+    pub fn message(&self) -> &'a str {
+        self.message
+    }
+
+    /// The encoded frame of the `interned` feature
+    #[cfg(feature = "interned")]
+    pub fn frame(&self) -> &'a [u8] {
+        self.frame
+    }
+
+    /// The message as bytes. This is the formatted message, or the encoded frame of the `interned`
+    /// feature
+    pub fn bytes(&self) -> &'a [u8] {
+        #[cfg(not(feature = "interned"))]
+        return self.message.as_bytes();
+        #[cfg(feature = "interned")]
+        return self.frame;
+    }
+
+    /// Structured fields passed to the trace macro, such as `temp` and `rpm` in
+    /// `trace_info!(temp = t, rpm = r; "motor update")`
+    pub fn fields(&self) -> &'a [Field<'a>] {
+        self.fields
+    }
+}
+
+/// A key and a typed value. Displayed as `key=value`
+#[derive(Clone, Copy)]
+pub struct Field<'a> {
+    key: &'static str,
+    value: Value<'a>,
+}
+
+impl<'a> Field<'a> {
+    pub const fn new(key: &'static str, value: Value<'a>) -> Self {
+        Self { key, value }
+    }
+
+    pub fn key(&self) -> &'static str {
+        self.key
+    }
+
+    pub fn value(&self) -> Value<'a> {
+        self.value
+    }
+}
+
+impl fmt::Display for Field<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// Value of a field. Primitives and strings convert to their own variant with `From`, while other
+/// types can be passed as `Value::Display(&value)` or `Value::Debug(&value)`
+#[derive(Clone, Copy)]
+pub enum Value<'a> {
+    Unsigned(u64),
+    Signed(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(&'a str),
+    Display(&'a dyn fmt::Display),
+    Debug(&'a dyn fmt::Debug),
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unsigned(value) => write!(f, "{}", value),
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::F32(value) => write!(f, "{}", value),
+            Value::F64(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::Display(value) => write!(f, "{}", value),
+            Value::Debug(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($variant:ident, $target:ty, $($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value<'_> {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(value as $target)
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(Unsigned, u64, u8, u16, u32, u64, usize);
+impl_from!(Signed, i64, i8, i16, i32, i64, isize);
+impl_from!(F32, f32, f32);
+impl_from!(F64, f64, f64);
+impl_from!(Bool, bool, bool);
+impl_from!(Char, char, char);
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(value: &'a str) -> Self {
+        Value::Str(value)
+    }
+}
@@ -0,0 +1,260 @@
+//! Protection against traces made while another trace is being handled, such as from an interrupt
+//! or from within the trace handler. Handlers are never entered twice, and nested traces are
+//! handled by the [`ReentrancyPolicy`]
+//!
+//! The guard, the queue and the marker count are kept per core, using the core ID registered with
+//! `#[trace_core_id]`. Without one, all cores share the state of core 0, and traces from one core
+//! while another is handling a trace are handled as nested traces as well
+
+use core::{
+    cell::UnsafeCell,
+    fmt::Write,
+    sync::atomic::{
+        AtomicBool, AtomicU8, AtomicUsize, Ordering::Acquire, Ordering::Relaxed, Ordering::Release,
+    },
+};
+
+use crate::{
+    Callsite, Level, MAX_CORES, Record, StackString, Styled, Timestamp, core_id,
+    handler::dispatch_unguarded,
+};
+
+/// Number of nested traces that can be queued with [`ReentrancyPolicy::Queue`]
+pub const NESTED_QUEUE_LENGTH: usize = 4;
+/// Maximum length of a queued nested message. Longer messages are cut and end with the truncation
+/// marker, and longer frames in interned mode are dropped
+pub const NESTED_MESSAGE_SIZE: usize = 128;
+// Longer markers are cut
+const MARKER_SIZE: usize = 128;
+
+/// What to do with a trace made while another trace is being handled
+#[repr(u8)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ReentrancyPolicy {
+    /// Drop nested traces. This is the default
+    Drop,
+    /// Drop nested traces, and trace a warning with the number of dropped traces once the outer
+    /// trace has been handled
+    Marker,
+    /// Queue nested traces, and pass them on once the outer trace has been handled. Their fields
+    /// are not kept. Traces are dropped if the queue is full
+    Queue,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(ReentrancyPolicy::Drop as u8);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+static GUARDS: [Guard; MAX_CORES] = [const { Guard::new() }; MAX_CORES];
+
+static MARKER_CALLSITE: Callsite = Callsite::new(module_path!(), module_path!(), file!(), line!());
+
+pub fn set_reentrancy_policy(policy: ReentrancyPolicy) {
+    POLICY.store(policy as u8, Relaxed);
+}
+
+pub fn reentrancy_policy() -> ReentrancyPolicy {
+    match POLICY.load(Relaxed) {
+        1 => ReentrancyPolicy::Marker,
+        2 => ReentrancyPolicy::Queue,
+        _ => ReentrancyPolicy::Drop,
+    }
+}
+
+/// Number of nested traces that have been dropped since startup, on all cores
+pub fn nested_dropped() -> usize {
+    DROPPED.load(Relaxed)
+}
+
+/// Reentrancy state of one core
+struct Guard {
+    active: AtomicBool,
+    // Dropped since the last marker
+    marker_dropped: AtomicUsize,
+    queue: Queue,
+}
+
+impl Guard {
+    const fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            marker_dropped: AtomicUsize::new(0),
+            queue: Queue::new(),
+        }
+    }
+}
+
+/// Guard of the current core
+fn guard() -> &'static Guard {
+    &GUARDS[core_id()]
+}
+
+/// Mark the start of handling a trace. Returns false if another trace is already being handled
+pub(crate) fn enter() -> bool {
+    guard()
+        .active
+        .compare_exchange(false, true, Acquire, Relaxed)
+        .is_ok()
+}
+
+/// Check if a trace is being handled on the current core
+#[cfg(any(feature = "panic-handler", test))]
+pub(crate) fn active() -> bool {
+    guard().active.load(Relaxed)
+}
+
+/// Handle a record traced while another trace is being handled
+pub(crate) fn nested(record: &Record) {
+    if reentrancy_policy() != ReentrancyPolicy::Queue || !guard().queue.push(record) {
+        drop_nested();
+    }
+}
+
+/// Count a nested trace that couldn't be handled
+pub(crate) fn drop_nested() {
+    DROPPED.fetch_add(1, Relaxed);
+    if reentrancy_policy() == ReentrancyPolicy::Marker {
+        guard().marker_dropped.fetch_add(1, Relaxed);
+    }
+}
+
+/// Mark the end of handling a trace, after passing on the queued traces and the marker. Traces
+/// queued after the last check are passed on by the next trace
+pub(crate) fn exit() {
+    let guard = guard();
+    while let Some(entry) = guard.queue.pop() {
+        dispatch_unguarded(&entry.record());
+    }
+
+    let dropped = guard.marker_dropped.swap(0, Relaxed);
+    if dropped > 0 {
+        // Formatted on the stack, as the format buffer may be in use by the outer trace
+        let timestamp = Timestamp::now();
+        let mut marker = StackString::<MARKER_SIZE>::new();
+        let _ = write!(
+            marker,
+            "{}",
+            Styled::new(
+                Level::Warning,
+                timestamp,
+                &MARKER_CALLSITE,
+                format_args!("[{} nested traces dropped]", dropped)
+            )
+        );
+        marker.with_record(
+            Level::Warning,
+            timestamp,
+            &MARKER_CALLSITE,
+            dispatch_unguarded,
+        );
+    }
+
+    guard.active.store(false, Release);
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    level: Level,
+    timestamp: Timestamp,
+    callsite: Callsite,
+    length: usize,
+    bytes: [u8; NESTED_MESSAGE_SIZE],
+}
+
+impl Entry {
+    const fn new() -> Self {
+        Self {
+            level: Level::Debug,
+            timestamp: Timestamp::from_ticks(0),
+            callsite: Callsite::new("", "", "", 0),
+            length: 0,
+            bytes: [0; NESTED_MESSAGE_SIZE],
+        }
+    }
+
+    fn record(&self) -> Record<'_> {
+        Record::stored(
+            self.level,
+            self.timestamp,
+            &self.callsite,
+            &self.bytes[..self.length],
+        )
+    }
+}
+
+struct Entries {
+    entries: [Entry; NESTED_QUEUE_LENGTH],
+    start: usize,
+    count: usize,
+}
+
+/// Queue of nested traces. Like the ring buffer, it is never waited on, as it is pushed to from
+/// interrupts
+struct Queue {
+    locked: AtomicBool,
+    entries: UnsafeCell<Entries>,
+}
+
+// The entries are only accessed while holding the lock
+unsafe impl Sync for Queue {}
+
+impl Queue {
+    const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            entries: UnsafeCell::new(Entries {
+                entries: [const { Entry::new() }; NESTED_QUEUE_LENGTH],
+                start: 0,
+                count: 0,
+            }),
+        }
+    }
+
+    /// Copy a record into the queue. Returns false if it was dropped
+    fn push(&self, record: &Record) -> bool {
+        if !record.can_store(NESTED_MESSAGE_SIZE) {
+            return false;
+        }
+
+        self.with(|entries| {
+            if entries.count == NESTED_QUEUE_LENGTH {
+                return false;
+            }
+
+            let entry = &mut entries.entries[(entries.start + entries.count) % NESTED_QUEUE_LENGTH];
+            entry.level = record.level();
+            entry.timestamp = record.timestamp();
+            entry.callsite = *record.callsite();
+            entry.length = record.store(&mut entry.bytes);
+            entries.count += 1;
+            true
+        })
+        .unwrap_or(false)
+    }
+
+    fn pop(&self) -> Option<Entry> {
+        self.with(|entries| {
+            if entries.count == 0 {
+                return None;
+            }
+
+            let entry = entries.entries[entries.start];
+            entries.start = (entries.start + 1) % NESTED_QUEUE_LENGTH;
+            entries.count -= 1;
+            Some(entry)
+        })
+        .flatten()
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Entries) -> R) -> Option<R> {
+        if self
+            .locked
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+
+        let res = f(unsafe { &mut *self.entries.get() });
+        self.locked.store(false, Release);
+        Some(res)
+    }
+}
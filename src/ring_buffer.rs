@@ -0,0 +1,212 @@
+//! In-memory trace handler that keeps the most recent records, for inspection after a fault or in
+//! tests.
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{
+        AtomicBool, AtomicUsize, Ordering::Acquire, Ordering::Relaxed, Ordering::Release,
+    },
+};
+
+use crate::{Level, Record, TraceHandler};
+
+/// A stored record. Messages longer than the entry length are cut at a character boundary and end
+/// with the truncation marker, and longer frames in interned mode are dropped
+pub struct Entry<const LENGTH: usize> {
+    level: Level,
+    length: usize,
+    bytes: [u8; LENGTH],
+}
+
+impl<const LENGTH: usize> Entry<LENGTH> {
+    const fn new() -> Self {
+        Self {
+            level: Level::Debug,
+            length: 0,
+            bytes: [0; LENGTH],
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// The stored message, or the encoded frame of the `interned` feature
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+
+    /// The stored message as a string, up to the first invalid UTF-8 sequence
+    pub fn message(&self) -> &str {
+        match core::str::from_utf8(self.bytes()) {
+            Ok(message) => message,
+            Err(error) => unsafe {
+                core::str::from_utf8_unchecked(&self.bytes[..error.valid_up_to()])
+            },
+        }
+    }
+}
+
+struct Entries<const N: usize, const LENGTH: usize> {
+    entries: [Entry<LENGTH>; N],
+    // Index of the oldest entry
+    start: usize,
+    count: usize,
+}
+
+/// A trace handler keeping the last `N` records, each up to `LENGTH` bytes long. It can be
+/// registered with `#[trace_handler]`, `set_handler` or as a sink:
+///
+/// ```ignore
+/// static HISTORY: RingBuffer<32> = RingBuffer::new();
+///
+/// HISTORY.for_each(|entry| std::println!("{:?}: {}", entry.level(), entry.message()));
+/// ```
+///
+/// The buffer is never waited on, so that it can be used from interrupts. Records traced while the
+/// buffer is being read are dropped, and counted by [`RingBuffer::dropped`]
+pub struct RingBuffer<const N: usize, const LENGTH: usize = 128> {
+    locked: AtomicBool,
+    dropped: AtomicUsize,
+    // Number of stored records as of the last change, readable while the buffer is locked
+    count: AtomicUsize,
+    entries: UnsafeCell<Entries<N, LENGTH>>,
+}
+
+// The entries are only accessed while holding the lock
+unsafe impl<const N: usize, const LENGTH: usize> Sync for RingBuffer<N, LENGTH> {}
+
+impl<const N: usize, const LENGTH: usize> Default for RingBuffer<N, LENGTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const LENGTH: usize> RingBuffer<N, LENGTH> {
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            entries: UnsafeCell::new(Entries {
+                entries: [const { Entry::new() }; N],
+                start: 0,
+                count: 0,
+            }),
+        }
+    }
+
+    /// Store a message, replacing the oldest one if the buffer is full. Messages that don't fit in
+    /// an entry are cut like formatted messages, and counted by
+    /// [`truncated_messages`](crate::truncated_messages). Frames of the `interned` feature that
+    /// don't fit are dropped, as a cut frame can't be decoded
+    pub fn push(&self, level: Level, bytes: &[u8]) {
+        #[cfg(feature = "interned")]
+        if bytes.len() > LENGTH {
+            self.dropped.fetch_add(1, Relaxed);
+            return;
+        }
+        // Messages are only cut at character boundaries, so anything after an invalid sequence is
+        // left out
+        #[cfg(not(feature = "interned"))]
+        let message = match core::str::from_utf8(bytes) {
+            Ok(message) => message,
+            Err(error) => unsafe { core::str::from_utf8_unchecked(&bytes[..error.valid_up_to()]) },
+        };
+
+        let pushed = self.with(|entries| {
+            if N == 0 {
+                return;
+            }
+
+            let index = (entries.start + entries.count) % N;
+            if entries.count == N {
+                entries.start = (entries.start + 1) % N;
+            } else {
+                entries.count += 1;
+            }
+
+            let entry = &mut entries.entries[index];
+            entry.level = level;
+            entry.length = 0;
+            #[cfg(not(feature = "interned"))]
+            crate::append_or_truncate(&mut entry.bytes, &mut entry.length, message);
+            #[cfg(feature = "interned")]
+            {
+                entry.bytes[..bytes.len()].copy_from_slice(bytes);
+                entry.length = bytes.len();
+            }
+        });
+
+        if pushed.is_none() {
+            self.dropped.fetch_add(1, Relaxed);
+        }
+    }
+
+    /// Call `f` with every stored record, from oldest to newest. Returns false if the buffer was
+    /// busy
+    pub fn for_each(&self, mut f: impl FnMut(&Entry<LENGTH>)) -> bool {
+        self.with(|entries| {
+            for i in 0..entries.count {
+                f(&entries.entries[(entries.start + i) % N]);
+            }
+        })
+        .is_some()
+    }
+
+    /// Call `f` with every stored record, from oldest to newest, and remove them. Returns false if
+    /// the buffer was busy
+    pub fn drain(&self, mut f: impl FnMut(&Entry<LENGTH>)) -> bool {
+        self.with(|entries| {
+            for i in 0..entries.count {
+                f(&entries.entries[(entries.start + i) % N]);
+            }
+            entries.start = 0;
+            entries.count = 0;
+        })
+        .is_some()
+    }
+
+    /// Remove all stored records. Returns false if the buffer was busy
+    pub fn clear(&self) -> bool {
+        self.drain(|_| {})
+    }
+
+    /// Number of stored records. While the buffer is being written or read, this is the number of
+    /// records before that started
+    pub fn len(&self) -> usize {
+        self.count.load(Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of records dropped because they were traced while the buffer was busy, or because
+    /// their frame didn't fit in an entry in interned mode
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Relaxed)
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Entries<N, LENGTH>) -> R) -> Option<R> {
+        if self
+            .locked
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+
+        let entries = unsafe { &mut *self.entries.get() };
+        let res = f(entries);
+        self.count.store(entries.count, Release);
+        self.locked.store(false, Release);
+        Some(res)
+    }
+}
+
+impl<const N: usize, const LENGTH: usize> TraceHandler for RingBuffer<N, LENGTH> {
+    fn log(&self, record: &Record) {
+        self.push(record.level(), record.bytes());
+    }
+}
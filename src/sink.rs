@@ -0,0 +1,130 @@
+//! Registry of additional trace handlers. Every record is passed to the main trace handler, and to
+//! each registered sink whose level it passes.
+
+use core::{
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicU8, Ordering::AcqRel, Ordering::Acquire, Ordering::Relaxed,
+        Ordering::Release,
+    },
+};
+
+use crate::{Level, Record, TraceHandler};
+
+/// Maximum number of sinks that can be registered at the same time
+pub const MAX_SINKS: usize = 4;
+
+static SINKS: [AtomicPtr<Sink>; MAX_SINKS] = [const { AtomicPtr::new(ptr::null_mut()) }; MAX_SINKS];
+static ADDING: AtomicBool = AtomicBool::new(false);
+
+/// A trace handler with its own minimum level
+///
+/// ```ignore
+/// static STORAGE_SINK: Sink = Sink::new(&FLASH_LOG, Level::Error);
+/// trace::add_sink(&STORAGE_SINK).unwrap();
+/// ```
+pub struct Sink {
+    handler: &'static dyn TraceHandler,
+    level: AtomicU8,
+}
+
+impl Sink {
+    pub const fn new(handler: &'static dyn TraceHandler, level: Level) -> Self {
+        Self {
+            handler,
+            level: AtomicU8::new(level as u8),
+        }
+    }
+
+    /// Set the least severe level passed to this sink
+    pub fn set_level(&self, level: Level) {
+        self.level.store(level as u8, Relaxed);
+    }
+
+    pub fn level(&self) -> Level {
+        Level::try_from(self.level.load(Relaxed)).unwrap_or(Level::Trace)
+    }
+
+    fn enabled(&self, level: Level) -> bool {
+        level >= self.level() && self.handler.enabled(level)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SinkError {
+    /// All `MAX_SINKS` slots are in use
+    Full,
+    /// The sink is already registered
+    AlreadyAdded,
+    /// Another sink is being added at the same time, such as by an interrupt. Try again later
+    Busy,
+}
+
+/// Register a sink. Records are passed to it from the next trace on
+pub fn add_sink(sink: &'static Sink) -> Result<(), SinkError> {
+    // Sinks are added one at a time, so that the same sink can't pass the duplicate check in two
+    // contexts at once. Like the other locks of this crate, this never waits
+    if ADDING
+        .compare_exchange(false, true, Acquire, Relaxed)
+        .is_err()
+    {
+        return Err(SinkError::Busy);
+    }
+
+    let res = insert(ptr::from_ref(sink).cast_mut());
+    ADDING.store(false, Release);
+    res
+}
+
+fn insert(sink: *mut Sink) -> Result<(), SinkError> {
+    if SINKS.iter().any(|slot| slot.load(Acquire) == sink) {
+        return Err(SinkError::AlreadyAdded);
+    }
+
+    for slot in SINKS.iter() {
+        if slot
+            .compare_exchange(ptr::null_mut(), sink, AcqRel, Acquire)
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    Err(SinkError::Full)
+}
+
+/// Unregister a sink. Returns false if it wasn't registered
+pub fn remove_sink(sink: &'static Sink) -> bool {
+    let sink = ptr::from_ref(sink).cast_mut();
+    SINKS.iter().any(|slot| {
+        slot.compare_exchange(sink, ptr::null_mut(), AcqRel, Acquire)
+            .is_ok()
+    })
+}
+
+fn sinks() -> impl Iterator<Item = &'static Sink> {
+    // Slots are only ever set from `&'static Sink`
+    SINKS
+        .iter()
+        .filter_map(|slot| unsafe { slot.load(Acquire).as_ref() })
+}
+
+/// Check if any registered sink accepts a level
+pub(crate) fn sinks_enabled(level: Level) -> bool {
+    sinks().any(|sink| sink.enabled(level))
+}
+
+/// Pass a record on to every sink that accepts its level
+pub(crate) fn dispatch_sinks(record: &Record) {
+    for sink in sinks() {
+        if sink.enabled(record.level()) {
+            sink.handler.log(record);
+        }
+    }
+}
+
+/// Flush every registered sink
+pub(crate) fn flush_sinks() {
+    for sink in sinks() {
+        sink.handler.flush();
+    }
+}
@@ -0,0 +1,79 @@
+//! Handlers receiving messages in chunks as they are formatted, instead of as a formatted record.
+//! This avoids the format buffer, and with it the limit on message length
+
+use core::{
+    fmt, ptr,
+    sync::atomic::{AtomicPtr, Ordering::Acquire, Ordering::Release},
+};
+
+use crate::{Callsite, Field, Level};
+
+// Stored behind another reference, as a `&dyn StreamHandler` is two words and can't be swapped
+// atomically
+static STREAM_HANDLER: AtomicPtr<&'static dyn StreamHandler> = AtomicPtr::new(ptr::null_mut());
+
+/// A trace handler that accepts messages in chunks, such as a UART with a FIFO. Each message is
+/// passed as a call to `begin`, any number of calls to `write` and a call to `end`
+///
+/// ```ignore
+/// static UART_STREAM: &dyn StreamHandler = &UART;
+/// trace::set_stream_handler(&UART_STREAM);
+/// ```
+pub trait StreamHandler: Sync {
+    /// Start a message
+    fn begin(&self, level: Level, callsite: &Callsite, fields: &[Field]);
+
+    /// Write the next chunk of the message
+    fn write(&self, chunk: &str);
+
+    /// End the message
+    fn end(&self);
+
+    /// Check if messages of a level should be handled. Checked before the message is formatted
+    fn enabled(&self, _level: Level) -> bool {
+        true
+    }
+
+    /// Write out any buffered messages
+    fn flush(&self) {}
+}
+
+/// Register a stream handler, which is used instead of the trace handler while it is registered.
+/// Sinks still receive formatted records. Not available in interned mode, where messages are not
+/// formatted on the target
+pub fn set_stream_handler(handler: &'static &'static dyn StreamHandler) {
+    STREAM_HANDLER.store(ptr::from_ref(handler).cast_mut(), Release);
+}
+
+/// Remove the stream handler, going back to the trace handler
+pub fn clear_stream_handler() {
+    STREAM_HANDLER.store(ptr::null_mut(), Release);
+}
+
+pub(crate) fn stream_handler() -> Option<&'static dyn StreamHandler> {
+    // Only ever set from `&'static &'static dyn StreamHandler`
+    unsafe { STREAM_HANDLER.load(Acquire).as_ref().copied() }
+}
+
+/// Adapter passing every formatted piece of a message on to a stream handler
+struct StreamWriter<'a>(&'a dyn StreamHandler);
+
+impl fmt::Write for StreamWriter<'_> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.0.write(string);
+        Ok(())
+    }
+}
+
+/// Format a message directly into a stream handler
+pub(crate) fn stream(
+    handler: &dyn StreamHandler,
+    callsite: &Callsite,
+    level: Level,
+    fields: &[Field],
+    args: fmt::Arguments,
+) {
+    handler.begin(level, callsite, fields);
+    let _ = fmt::write(&mut StreamWriter(handler), args);
+    handler.end();
+}
@@ -0,0 +1,195 @@
+//! Layout of the messages of the leveled trace macros, such as [`trace_info!`](crate::trace_info).
+//! The style sets the label and color of every level, and which parts make up a message in which
+//! order. It can be defined as a static and registered at runtime:
+//!
+//! ```ignore
+//! static STYLE: Style = Style {
+//!     labels: ["T ", "D ", "I ", "W ", "E ", "F "],
+//!     colors: [Color::Fixed(244); 6],
+//!     parts: &[Part::Uptime(1_000_000), Part::Text(" "), Part::Label, Part::Message],
+//!     ..Style::DEFAULT
+//! };
+//!
+//! trace::set_style(&STYLE);
+//! ```
+
+use core::{
+    fmt, ptr,
+    sync::atomic::{AtomicPtr, Ordering::Acquire, Ordering::Release},
+};
+
+use crate::{Callsite, Level, Timestamp};
+
+static STYLE: AtomicPtr<Style> = AtomicPtr::new(ptr::null_mut());
+
+/// ANSI color of a level
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Color {
+    /// No escape sequences at all
+    None,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// A color of the 256 color palette
+    Fixed(u8),
+}
+
+impl Color {
+    fn start(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            Color::None => return Ok(()),
+            Color::Black => 30,
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+            Color::White => 37,
+            Color::Fixed(color) => return write!(f, "\x1b[38;5;{}m", color),
+        };
+        write!(f, "\x1b[{}m", code)
+    }
+
+    fn end(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::None => Ok(()),
+            _ => f.write_str("\x1b[0m"),
+        }
+    }
+}
+
+/// A part of a message
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Part {
+    /// The label of the level, such as `INFO: `
+    Label,
+    /// The formatted message
+    Message,
+    /// The timestamp in ticks, such as `[12345678]`
+    Timestamp,
+    /// The timestamp as uptime for a number of ticks per second, such as `[  12.345678]`
+    Uptime(u64),
+    /// The file and line of the trace, such as `src/main.rs:42`
+    Location,
+    /// The target of the trace, which defaults to its module path
+    Target,
+    /// Fixed text, such as a separator
+    Text(&'static str),
+}
+
+/// Layout of the messages of the leveled trace macros. Levels are indexed by their
+/// [severity](Level::severity), from trace to fatal
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Style {
+    pub labels: [&'static str; 6],
+    /// Color of the whole message except for the line end
+    pub colors: [Color; 6],
+    pub parts: &'static [Part],
+    pub line_end: &'static str,
+}
+
+impl Style {
+    /// The style used until another one is registered, such as `INFO: message` in green
+    pub const DEFAULT: Style = Style {
+        labels: [
+            "TRACE: ",
+            "DEBUG: ",
+            "INFO: ",
+            "WARNING: ",
+            "ERROR: ",
+            "FATAL: ",
+        ],
+        #[cfg(not(feature = "no-color"))]
+        colors: [
+            Color::Blue,
+            Color::Magenta,
+            Color::Green,
+            Color::Yellow,
+            Color::Red,
+            Color::Red,
+        ],
+        #[cfg(feature = "no-color")]
+        colors: [Color::None; 6],
+        parts: &[Part::Label, Part::Message],
+        line_end: "\r\n",
+    };
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Register the style of the leveled trace macros. In interned mode, it only applies to messages
+/// formatted on the target, as the macros intern their labels with the format string
+pub fn set_style(style: &'static Style) {
+    STYLE.store(ptr::from_ref(style).cast_mut(), Release);
+}
+
+/// Go back to [`Style::DEFAULT`]
+pub fn clear_style() {
+    STYLE.store(ptr::null_mut(), Release);
+}
+
+pub fn style() -> &'static Style {
+    // Only ever set from `&'static Style`
+    unsafe { STYLE.load(Acquire).as_ref() }.unwrap_or(&Style::DEFAULT)
+}
+
+/// A message laid out with the registered [`Style`], as traced by the leveled trace macros
+pub struct Styled<'a> {
+    level: Level,
+    timestamp: Timestamp,
+    callsite: &'a Callsite,
+    message: fmt::Arguments<'a>,
+}
+
+impl<'a> Styled<'a> {
+    /// Lay out a message traced at `timestamp`, which is shown by [`Part::Timestamp`] and
+    /// [`Part::Uptime`]
+    pub fn new(
+        level: Level,
+        timestamp: Timestamp,
+        callsite: &'a Callsite,
+        message: fmt::Arguments<'a>,
+    ) -> Self {
+        Self {
+            level,
+            timestamp,
+            callsite,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Styled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = style();
+        let index = self.level.severity() as usize;
+        let color = style.colors[index];
+
+        color.start(f)?;
+        for part in style.parts {
+            match part {
+                Part::Label => f.write_str(style.labels[index])?,
+                Part::Message => f.write_fmt(self.message)?,
+                Part::Timestamp => write!(f, "{}", self.timestamp)?,
+                Part::Uptime(ticks_per_second) => {
+                    write!(f, "{}", self.timestamp.uptime(*ticks_per_second))?
+                }
+                Part::Location => write!(f, "{}:{}", self.callsite.file(), self.callsite.line())?,
+                Part::Target => f.write_str(self.callsite.target())?,
+                Part::Text(text) => f.write_str(text)?,
+            }
+        }
+        color.end(f)?;
+        f.write_str(style.line_end)
+    }
+}
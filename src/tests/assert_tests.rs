@@ -0,0 +1,86 @@
+use std::{string::String, vec::Vec};
+
+use crate::{
+    AssertAction, Level, RingBuffer, Sink, add_sink, assert_action, remove_sink, set_assert_action,
+    trace_assert, trace_assert_eq, trace_debug_assert,
+};
+
+static BUFFER: RingBuffer<4, 256> = RingBuffer::new();
+static SINK: Sink = Sink::new(&BUFFER, Level::Debug);
+
+/// Run `f` with the failed assertions collected by a sink. Also returns the panic message if `f`
+/// panicked
+fn failures(action: AssertAction, f: impl FnOnce()) -> (Vec<String>, Option<String>) {
+    critical_section::with(|_| {
+        set_assert_action(action);
+        assert_eq!(Ok(()), add_sink(&SINK));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        // Failures are only passed on to the sink once drained
+        #[cfg(feature = "deferred")]
+        crate::drain_to_handlers();
+        assert!(remove_sink(&SINK));
+        set_assert_action(AssertAction::Panic);
+
+        let mut messages = Vec::new();
+        BUFFER.drain(|entry| messages.push(super::text(entry.bytes())));
+        let panic = result
+            .err()
+            .map(|payload| match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => String::from(*payload.downcast::<&str>().unwrap()),
+            });
+        (messages, panic)
+    })
+}
+
+#[test]
+fn assert_passes() {
+    let (messages, panic) = failures(AssertAction::Panic, || {
+        trace_assert!(1 + 1 == 2);
+        trace_assert_eq!(2, 1 + 1, "math");
+        trace_debug_assert!(true);
+    });
+    assert!(messages.is_empty());
+    assert_eq!(None, panic);
+}
+
+#[test]
+fn assert_traces_failures() {
+    let line = line!() + 3;
+    let (messages, panic) = failures(AssertAction::Continue, || {
+        let value = 3;
+        trace_assert!(value < 2);
+        trace_assert!(value < 1, "value {} too large", value);
+        trace_assert_eq!(value, 4);
+        trace_debug_assert!(value == 0);
+    });
+
+    assert_eq!(None, panic);
+    // `trace_debug_assert!` is only checked with debug assertions
+    assert_eq!(3 + cfg!(debug_assertions) as usize, messages.len());
+    assert!(messages[0].contains(&std::format!(
+        "assertion failed: value < 2 at src/tests/assert_tests.rs:{}",
+        line
+    )));
+    assert!(messages[1].contains("assertion failed: value < 1: value 3 too large at"));
+    assert!(messages[2].contains("assertion failed: value == 4 (left: 3, right: 4) at"));
+    if cfg!(debug_assertions) {
+        assert!(messages[3].contains("assertion failed: value == 0 at"));
+    }
+    assert!(messages.iter().all(|message| message.ends_with("\r\n")));
+}
+
+#[test]
+fn assert_panics_after_tracing() {
+    assert_eq!(AssertAction::Panic, assert_action());
+    let (messages, panic) = failures(AssertAction::Panic, || trace_assert_eq!(1, 2, "first"));
+    assert_eq!(1, messages.len());
+    assert!(messages[0].contains("(left: 1, right: 2): first"));
+    // The panic handler of the crate traces the panic as well, so it doesn't repeat the message
+    let expected = if cfg!(feature = "panic-handler") {
+        "assertion failed, traced above"
+    } else {
+        "assertion failed: 1 == 2 (left: 1, right: 2): first"
+    };
+    assert_eq!(Some(String::from(expected)), panic);
+}
@@ -0,0 +1,205 @@
+use std::{sync::Arc, thread, vec::Vec};
+
+use crate::{Callsite, DeferredQueue, Level, Record, Timestamp};
+
+static CALLSITE: Callsite = Callsite::new("deferred", "deferred", "deferred_tests.rs", 1);
+
+fn push<const N: usize, const LENGTH: usize>(
+    queue: &DeferredQueue<N, LENGTH>,
+    level: Level,
+    ticks: u64,
+    bytes: &[u8],
+) -> bool {
+    queue.push(&Record::stored(
+        level,
+        Timestamp::from_ticks(ticks),
+        &CALLSITE,
+        bytes,
+    ))
+}
+
+fn drained<const N: usize, const LENGTH: usize>(
+    queue: &DeferredQueue<N, LENGTH>,
+) -> Vec<(Level, u64, Vec<u8>)> {
+    let mut records = Vec::new();
+    queue.drain(|record| {
+        assert_eq!(&CALLSITE, record.callsite());
+        records.push((
+            record.level(),
+            record.timestamp().ticks(),
+            Vec::from(record.bytes()),
+        ));
+    });
+    records
+}
+
+#[test]
+fn deferred_queue_keeps_order_and_timestamps() {
+    let queue: DeferredQueue<4> = DeferredQueue::new();
+    assert!(queue.is_empty());
+
+    assert!(push(&queue, Level::Debug, 1, b"first"));
+    assert!(push(&queue, Level::Error, 2, b"second"));
+    assert_eq!(2, queue.len());
+    assert_eq!(
+        vec![
+            (Level::Debug, 1, Vec::from(*b"first")),
+            (Level::Error, 2, Vec::from(*b"second")),
+        ],
+        drained(&queue)
+    );
+    assert!(queue.is_empty());
+    assert!(drained(&queue).is_empty());
+}
+
+#[test]
+fn deferred_queue_drops_when_full() {
+    let queue: DeferredQueue<2> = DeferredQueue::new();
+
+    // Going around several times reuses the slots
+    for round in 0..3 {
+        assert!(push(&queue, Level::Info, round, b"first"));
+        assert!(push(&queue, Level::Info, round, b"second"));
+        assert!(!push(&queue, Level::Info, round, b"third"));
+        assert_eq!(round as usize + 1, queue.dropped());
+        assert_eq!(
+            vec![
+                (Level::Info, round, Vec::from(*b"first")),
+                (Level::Info, round, Vec::from(*b"second")),
+            ],
+            drained(&queue)
+        );
+    }
+}
+
+#[test]
+fn deferred_queue_truncates_long_messages() {
+    // Messages are cut like formatted messages, at a character boundary and with the marker
+    #[cfg(not(feature = "interned"))]
+    critical_section::with(|_| {
+        let queue: DeferredQueue<2, 16> = DeferredQueue::new();
+        let truncated = crate::truncated_messages();
+        assert!(push(
+            &queue,
+            Level::Info,
+            0,
+            "x\u{e9}xxxxxxxxxxxxxxx".as_bytes()
+        ));
+        assert_eq!(
+            vec![(Level::Info, 0, Vec::from("x…[truncated]".as_bytes()))],
+            drained(&queue)
+        );
+        assert_eq!(truncated + 1, crate::truncated_messages());
+    });
+    // Frames can't be cut
+    #[cfg(feature = "interned")]
+    {
+        let queue: DeferredQueue<2, 4> = DeferredQueue::new();
+        assert!(!push(&queue, Level::Info, 0, b"abcdef"));
+        assert_eq!(1, queue.dropped());
+    }
+}
+
+#[test]
+fn deferred_queue_leaves_records_pushed_while_draining() {
+    let queue: DeferredQueue<4> = DeferredQueue::new();
+    push(&queue, Level::Info, 0, b"first");
+
+    let mut count = 0;
+    queue.drain(|_| {
+        count += 1;
+        // A nested drain is refused, as there is only one reader at a time
+        assert_eq!(0, queue.drain(|_| {}));
+        push(&queue, Level::Info, 1, b"nested");
+    });
+    assert_eq!(1, count);
+    assert_eq!(
+        vec![(Level::Info, 1, Vec::from(*b"nested"))],
+        drained(&queue)
+    );
+}
+
+#[test]
+fn deferred_queue_concurrent_writers() {
+    const WRITERS: u64 = 4;
+    const RECORDS: u64 = 2000;
+
+    let queue: Arc<DeferredQueue<16, 16>> = Arc::new(DeferredQueue::new());
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 0..RECORDS {
+                    push(&queue, Level::Info, i, &writer.to_le_bytes());
+                }
+            })
+        })
+        .collect();
+
+    let mut last = [None; WRITERS as usize];
+    let mut received = 0;
+    let mut check = |record: &Record| {
+        let writer = u64::from_le_bytes(record.bytes().try_into().unwrap()) as usize;
+        let ticks = record.timestamp().ticks();
+        // Records of each writer arrive in order
+        assert!(last[writer].is_none_or(|last| last < ticks));
+        last[writer] = Some(ticks);
+        received += 1;
+    };
+    while !writers.iter().all(|writer| writer.is_finished()) {
+        queue.drain(&mut check);
+    }
+    queue.drain(&mut check);
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    assert_eq!((WRITERS * RECORDS) as usize, received + queue.dropped());
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[cfg(all(feature = "deferred", not(feature = "interned")))]
+#[test]
+fn deferred_feature_queues_traces() {
+    critical_section::with(|_| {
+        // Records left over by other tests
+        crate::drain(|_| {});
+        crate::trace_info!("deferred {}", 1);
+        crate::trace_error!("deferred {}", 2);
+
+        let mut messages = Vec::new();
+        assert_eq!(
+            2,
+            crate::drain(|record| messages.push(std::string::String::from(record.message())))
+        );
+        assert!(messages[0].contains("deferred 1"));
+        assert!(messages[1].contains("deferred 2"));
+        assert_eq!(0, crate::drain(|_| {}));
+    });
+}
+
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
+#[cfg(all(feature = "deferred", not(feature = "interned")))]
+#[test]
+fn deferred_feature_drains_to_handlers() {
+    use crate::{RingBuffer, Sink, add_sink, remove_sink};
+
+    critical_section::with(|_| {
+        static BUFFER: RingBuffer<4> = RingBuffer::new();
+        static SINK: Sink = Sink::new(&BUFFER, Level::Debug);
+        assert_eq!(Ok(()), add_sink(&SINK));
+        // Records left over by other tests
+        crate::drain(|_| {});
+
+        crate::trace_warning!("deferred");
+        // Nothing is passed on until the queue is drained
+        assert!(BUFFER.is_empty());
+        assert_eq!(1, crate::drain_to_handlers());
+        assert_eq!(1, BUFFER.len());
+
+        assert!(remove_sink(&SINK));
+    });
+}
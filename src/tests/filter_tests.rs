@@ -0,0 +1,50 @@
+use crate::{Filter, Level};
+
+static FILTER: Filter = Filter::new("net=debug, storage=warning,net::tcp=off,*=info,invalid,x=y");
+
+#[test]
+fn filter_matches_exact_target() {
+    assert_eq!(Some(true), FILTER.enabled(Level::Debug, "net"));
+    assert_eq!(Some(false), FILTER.enabled(Level::Info, "storage"));
+    assert_eq!(Some(true), FILTER.enabled(Level::Warning, "storage"));
+}
+
+#[test]
+fn filter_matches_submodules() {
+    assert_eq!(Some(true), FILTER.enabled(Level::Debug, "net::udp"));
+    assert_eq!(Some(false), FILTER.enabled(Level::Error, "net::tcp"));
+    assert_eq!(
+        Some(false),
+        FILTER.enabled(Level::Error, "net::tcp::socket")
+    );
+}
+
+#[test]
+fn filter_falls_back_to_wildcard() {
+    assert_eq!(Some(false), FILTER.enabled(Level::Debug, "network"));
+    assert_eq!(Some(true), FILTER.enabled(Level::Info, "storage_driver"));
+}
+
+#[test]
+fn filter_without_match() {
+    let filter = Filter::new("net=debug");
+    assert_eq!(None, filter.enabled(Level::Debug, "storage"));
+    assert_eq!(None, Filter::new("").enabled(Level::Debug, "net"));
+}
+
+#[test]
+fn filter_ignores_directives_over_limit() {
+    static FILTER: Filter = Filter::new(
+        "t0=info,t1=info,t2=info,t3=info,t4=info,t5=info,t6=info,t7=info,t8=info,t9=info,t10=info,t11=info,t12=info,t13=info,t14=info,t15=info,late=off",
+    );
+    assert_eq!(Some(true), FILTER.enabled(Level::Info, "t15"));
+    assert_eq!(None, FILTER.enabled(Level::Info, "late"));
+}
+
+#[test]
+fn filter_trace_and_fatal() {
+    static FILTER: Filter = Filter::new("net=trace,storage=fatal");
+    assert_eq!(Some(true), FILTER.enabled(Level::Trace, "net"));
+    assert_eq!(Some(false), FILTER.enabled(Level::Error, "storage"));
+    assert_eq!(Some(true), FILTER.enabled(Level::Fatal, "storage"));
+}
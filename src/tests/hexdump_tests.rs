@@ -0,0 +1,61 @@
+use std::format;
+
+use crate::hexdump::{ROW_TEXT_LENGTH, Row};
+
+#[test]
+fn hexdump_full_row() {
+    let row = Row {
+        offset: 0x10,
+        bytes: b"Hello, world!\r\n\0",
+    };
+    assert_eq!(
+        "00000010  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0d 0a 00  |Hello, world!...|",
+        format!("{}", row)
+    );
+    assert_eq!(ROW_TEXT_LENGTH, format!("{}", row).len());
+}
+
+#[test]
+fn hexdump_short_row_is_padded() {
+    let row = Row {
+        offset: 0x20,
+        bytes: &[0x7f, b'a', 0xff],
+    };
+    assert_eq!(
+        "00000020  7f 61 ff                                          |.a.|",
+        format!("{}", row)
+    );
+}
+
+// Checks the formatted text, which is not produced in interned mode, and is only passed on when
+// drained in deferred mode
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[cfg(not(any(feature = "interned", feature = "deferred")))]
+#[test]
+fn hexdump_splits_rows() {
+    use std::{string::String, vec::Vec};
+
+    use crate::{Level, RingBuffer, Sink, add_sink, remove_sink, trace_hexdump};
+
+    critical_section::with(|_| {
+        static BUFFER: RingBuffer<8> = RingBuffer::new();
+        static SINK: Sink = Sink::new(&BUFFER, Level::Debug);
+        assert_eq!(Ok(()), add_sink(&SINK));
+
+        let bytes: Vec<u8> = (0..40).collect();
+        trace_hexdump!(Level::Info, "frame", &bytes);
+        assert!(remove_sink(&SINK));
+
+        let mut messages = Vec::new();
+        BUFFER.drain(|entry| messages.push(String::from(entry.message())));
+        assert_eq!(4, messages.len());
+        assert!(messages[0].contains("frame (40 bytes)"));
+        assert!(messages[1].contains(
+            "00000000  00 01 02 03 04 05 06 07  08 09 0a 0b 0c 0d 0e 0f  |................|"
+        ));
+        assert!(messages[2].contains("00000010  10 11"));
+        assert!(messages[3].contains("00000020  20 21 22 23 24 25 26 27     "));
+        assert!(messages[3].contains("| !\"#$%&'|"));
+        assert!(messages.iter().all(|message| message.ends_with("\r\n")));
+    });
+}
@@ -0,0 +1,219 @@
+use crate::{
+    __trace_intern, Level, TRACE_FORMAT_BUFFER_SIZE, TraceString,
+    interned::{DecodeError, Decoder, Frame, RAW_INDEX, intern},
+};
+
+const SECTION: &[u8] =
+    b"Hello, {}!\0{} {:?} {} {}\0{:>5}|{:<5}|{:^5}|{:05}|{:#x}|{:+}|{:.2}\0{1} {0}\0";
+const ADDRESS: usize = 0x1000;
+
+fn decode(frame: &Frame) -> Result<(Level, TraceString), DecodeError> {
+    let mut res = TraceString::new();
+    let level = Decoder::new(SECTION, ADDRESS).decode(frame.as_bytes(), &mut res)?;
+    Ok((level, res))
+}
+
+#[test]
+fn intern_terminates_string() {
+    const INTERNED: [u8; 6] = intern("Hello");
+    assert_eq!(&INTERNED, b"Hello\0");
+}
+
+#[test]
+fn decode_string_argument() {
+    let mut frame = Frame::new(Level::Warning, ADDRESS);
+    frame.push(&"World");
+
+    let (level, res) = decode(&frame).unwrap();
+    assert_eq!(Level::Warning, level);
+    assert_eq!("Hello, World!", res.to_string());
+}
+
+#[test]
+fn decode_typed_arguments() {
+    let mut frame = Frame::new(Level::Info, ADDRESS + 11);
+    frame.push(&-1234i32);
+    frame.push(&'c');
+    frame.push(&true);
+    frame.push(&1.5f32);
+
+    let (_, res) = decode(&frame).unwrap();
+    assert_eq!("-1234 'c' true 1.5", res.to_string());
+}
+
+#[test]
+fn decode_format_specs() {
+    let mut frame = Frame::new(Level::Debug, ADDRESS + 25);
+    frame.push(&42u8);
+    frame.push(&"ab");
+    frame.push(&"ab");
+    frame.push(&-42i64);
+    frame.push(&255u32);
+    frame.push(&7u16);
+    frame.push(&1.23456f64);
+
+    let (_, res) = decode(&frame).unwrap();
+    assert_eq!("   42|ab   | ab  |-0042|0xff|+7|1.23", res.to_string());
+}
+
+#[test]
+fn decode_explicit_positions() {
+    let mut frame = Frame::new(Level::Debug, ADDRESS + 66);
+    frame.push(&1u8);
+    frame.push(&2u8);
+
+    let (_, res) = decode(&frame).unwrap();
+    assert_eq!("2 1", res.to_string());
+}
+
+#[test]
+fn decode_raw_text() {
+    let mut frame = Frame::new(Level::Error, RAW_INDEX);
+    frame.push_str("Already formatted");
+
+    let (level, res) = decode(&frame).unwrap();
+    assert_eq!(Level::Error, level);
+    assert_eq!("Already formatted", res.to_string());
+}
+
+#[test]
+fn decode_errors() {
+    let frame = Frame::new(Level::Info, ADDRESS);
+    assert_eq!(DecodeError::MissingArgument, decode(&frame).err().unwrap());
+
+    let frame = Frame::new(Level::Info, ADDRESS + 0x100);
+    assert_eq!(DecodeError::UnknownIndex, decode(&frame).err().unwrap());
+
+    let mut res = TraceString::new();
+    let decoder = Decoder::new(SECTION, ADDRESS);
+    assert_eq!(
+        DecodeError::InvalidLevel,
+        decoder.decode(&[0xff, 0], &mut res).err().unwrap()
+    );
+    assert_eq!(
+        DecodeError::UnexpectedEnd,
+        decoder.decode(&[0, 0x80], &mut res).err().unwrap()
+    );
+}
+
+#[test]
+fn long_strings_are_cut_at_char_boundary() {
+    let long = "ö".repeat(TRACE_FORMAT_BUFFER_SIZE);
+    let mut frame = Frame::new(Level::Info, RAW_INDEX);
+    frame.push_str(&long);
+    frame.push(&1u8);
+
+    let (_, res) = decode(&frame).unwrap();
+    assert!(res.to_string().chars().all(|c| c == 'ö'));
+    assert!(res.length > TRACE_FORMAT_BUFFER_SIZE - 24);
+}
+
+#[cfg(feature = "interned")]
+#[test]
+fn formatted_text_is_written_into_frame() {
+    use crate::truncated_messages;
+
+    critical_section::with(|_| {
+        let mut frame = Frame::new(Level::Info, RAW_INDEX);
+        frame.push_formatted(format_args!("{} {}", "short", 1));
+        assert_eq!("short 1", decode(&frame).unwrap().1.to_string());
+
+        let truncated = truncated_messages();
+        let long = "ö".repeat(TRACE_FORMAT_BUFFER_SIZE);
+        let mut frame = Frame::new(Level::Info, RAW_INDEX);
+        frame.push_formatted(format_args!("{}", long));
+
+        let (_, res) = decode(&frame).unwrap();
+        let text = res.to_string();
+        assert!(text.ends_with("…[truncated]"), "{}", text);
+        assert!(
+            text.trim_end_matches("…[truncated]")
+                .chars()
+                .all(|c| c == 'ö')
+        );
+        // Only a split character is left over
+        assert!(frame.as_bytes().len() >= TRACE_FORMAT_BUFFER_SIZE - 1);
+        assert_eq!(truncated + 1, truncated_messages());
+    })
+}
+
+#[test]
+fn interned_string_can_be_decoded() {
+    let index = __trace_intern!(concat!("Interned ", "{}"));
+    let section = unsafe { core::slice::from_raw_parts(index as *const u8, 12) };
+
+    let mut frame = Frame::new(Level::Info, index);
+    frame.push(&7u8);
+
+    let mut res = TraceString::new();
+    Decoder::new(section, index)
+        .decode(frame.as_bytes(), &mut res)
+        .unwrap();
+    assert_eq!("Interned 7", res.to_string());
+}
+
+#[test]
+fn signed_bits_decode_in_twos_complement() {
+    let mut frame = Frame::new(Level::Info, ADDRESS + 25);
+    frame.push(&42u8);
+    frame.push(&"ab");
+    frame.push(&"ab");
+    frame.push(&-42i64);
+    frame.push_bits(&-1i8);
+    frame.push(&7u16);
+    frame.push(&1.23456f64);
+
+    let (_, res) = decode(&frame).unwrap();
+    assert_eq!("   42|ab   | ab  |-0042|0xff|+7|1.23", res.to_string());
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[cfg(feature = "interned")]
+#[test]
+fn trace_macros_decode_on_host() {
+    use std::{sync::Mutex, vec::Vec};
+
+    use crate::{
+        Record, TraceHandler, clear_handler, set_handler, trace, trace_format, trace_info,
+        trace_warning,
+    };
+
+    struct FrameHandler(Mutex<Vec<Vec<u8>>>);
+
+    impl TraceHandler for FrameHandler {
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(Vec::from(record.frame()));
+        }
+    }
+
+    critical_section::with(|_| {
+        static FRAMES: FrameHandler = FrameHandler(Mutex::new(Vec::new()));
+        static HANDLER: &dyn TraceHandler = &FRAMES;
+        set_handler(&HANDLER);
+
+        trace_info!("Sensor {} read {:.1} at {:#06x}", 3u8, 21.5f32, 0x1f0u16);
+        trace_warning!(target: "net", rssi = -70; "Signal {}dB, mask {:x}, {:08b}, {:X}", -70i8, -1i8, -2i8, -1i32);
+        trace!("{1} {0}", "world", "hello");
+        trace_format(Level::Error, format_args!("raw {}", 5));
+        #[cfg(feature = "deferred")]
+        crate::drain_to_handlers();
+        clear_handler();
+
+        let frames = FRAMES.0.lock().unwrap();
+        let decoded: Vec<_> = frames
+            .iter()
+            .map(|frame| super::decode_traced(frame))
+            .collect();
+        assert_eq!(4, decoded.len());
+        assert_eq!(Level::Info, decoded[0].0);
+        assert!(decoded[0].1.contains("INFO: Sensor 3 read 21.5 at 0x01f0"));
+        assert_eq!(Level::Warning, decoded[1].0);
+        assert!(
+            decoded[1]
+                .1
+                .contains("WARNING: Signal -70dB, mask ff, 11111110, FFFFFFFF")
+        );
+        assert_eq!((Level::Info, "hello world".into()), decoded[2]);
+        assert_eq!((Level::Error, "raw 5".into()), decoded[3]);
+    });
+}
@@ -0,0 +1,195 @@
+use core::cell::Cell;
+
+use crate::{
+    Level, trace, trace_debug, trace_error, trace_fatal, trace_hexdump, trace_info, trace_panic,
+    trace_trace, trace_warning,
+};
+
+// Uses the same cfg table as the level gates of the macros
+use crate::__trace_level_compiled_in as compiled_in;
+
+#[test]
+fn level_gates_follow_trace_profile() {
+    critical_section::with(|_| {
+        let count = Cell::new(0);
+        trace_error!("{}", {
+            count.set(count.get() + 1);
+            "gated"
+        });
+        #[cfg(feature = "deferred")]
+        crate::drain(|_| {});
+
+        // Arguments are only evaluated if the macro is compiled in
+        let traced = !cfg!(feature = "always-off")
+            && !cfg!(any(feature = "max-level-off", feature = "max-level-fatal"))
+            && (cfg!(feature = "always-on") || cfg!(debug_assertions));
+        assert_eq!(traced as usize, count.get());
+    });
+}
+
+/// Number of times the argument is evaluated by the traces of `f`
+fn evaluations(f: impl FnOnce(&dyn Fn() -> u8)) -> usize {
+    let count = Cell::new(0);
+    f(&|| {
+        count.set(count.get() + 1);
+        0
+    });
+    #[cfg(feature = "deferred")]
+    crate::drain(|_| {});
+    count.get()
+}
+
+// The argument is unused by the levels that are compiled out
+#[cfg_attr(
+    not(any(trace_release_level_trace, all(debug_assertions, trace_level_trace))),
+    allow(unused_variables)
+)]
+#[test]
+fn filtered_levels_are_compiled_out() {
+    critical_section::with(|_| {
+        let expected = |level| compiled_in(level) as usize;
+        assert_eq!(
+            expected(Level::Trace),
+            evaluations(|argument| trace_trace!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Debug),
+            evaluations(|argument| trace_debug!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Info),
+            evaluations(|argument| trace_info!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Info),
+            evaluations(|argument| trace!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Warning),
+            evaluations(|argument| trace_warning!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Error),
+            evaluations(|argument| trace_error!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Fatal),
+            evaluations(|argument| trace_fatal!("{}", argument()))
+        );
+        assert_eq!(
+            expected(Level::Fatal),
+            evaluations(|argument| trace_panic!("{}", argument()))
+        );
+    });
+}
+
+#[test]
+fn filtered_hexdumps_are_compiled_out() {
+    critical_section::with(|_| {
+        for level in Level::ALL {
+            assert_eq!(
+                compiled_in(level) as usize,
+                evaluations(|argument| trace_hexdump!(level, "dump", &[argument()]))
+            );
+        }
+    });
+}
+
+// Arguments have to implement `Encode` rather than `Display` in interned mode
+#[cfg(not(feature = "interned"))]
+#[test]
+fn filtered_levels_never_format_arguments() {
+    use core::fmt;
+
+    /// Counts how often it is formatted
+    #[cfg_attr(
+        not(any(trace_release_level_debug, all(debug_assertions, trace_level_debug))),
+        allow(dead_code)
+    )]
+    struct Formatted<'a>(&'a Cell<usize>);
+
+    impl fmt::Display for Formatted<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.set(self.0.get() + 1);
+            f.write_str("formatted")
+        }
+    }
+
+    critical_section::with(|_| {
+        let debug = Cell::new(0);
+        let error = Cell::new(0);
+        trace_debug!("{}", Formatted(&debug));
+        trace_error!("{}", Formatted(&error));
+        #[cfg(feature = "deferred")]
+        crate::drain(|_| {});
+
+        assert_eq!(compiled_in(Level::Debug) as usize, debug.get());
+        assert_eq!(compiled_in(Level::Error) as usize, error.get());
+    });
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn filtered_levels_skip_log_records() {
+    use log::Log;
+
+    critical_section::with(|_| {
+        for level in [
+            log::Level::Trace,
+            log::Level::Debug,
+            log::Level::Info,
+            log::Level::Warn,
+            log::Level::Error,
+        ] {
+            let metadata = log::Metadata::builder().level(level).build();
+            assert_eq!(
+                compiled_in(Level::from(level)),
+                crate::log_bridge::LOGGER.enabled(&metadata)
+            );
+        }
+    });
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn filtered_levels_skip_tracing_events() {
+    use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    use tracing_subscriber::prelude::*;
+
+    use crate::{Record, Sink, TraceHandler, add_sink, remove_sink};
+
+    /// Counts the records of each level
+    struct LevelCounter([AtomicUsize; 6]);
+
+    impl TraceHandler for LevelCounter {
+        fn log(&self, record: &Record) {
+            self.0[record.level().severity() as usize].fetch_add(1, Relaxed);
+        }
+    }
+
+    static COUNTER: LevelCounter = LevelCounter([const { AtomicUsize::new(0) }; 6]);
+    static SINK: Sink = Sink::new(&COUNTER, Level::Trace);
+
+    critical_section::with(|_| {
+        assert_eq!(Ok(()), add_sink(&SINK));
+        let subscriber = tracing_subscriber::registry().with(crate::tracing_bridge::TraceLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::trace!("trace");
+            tracing::debug!("debug");
+            tracing::info!("info");
+            tracing::warn!("warning");
+            tracing::error!("error");
+        });
+        #[cfg(feature = "deferred")]
+        crate::drain_to_handlers();
+        assert!(remove_sink(&SINK));
+
+        for level in &Level::ALL[..5] {
+            assert_eq!(
+                compiled_in(*level) as usize,
+                COUNTER.0[level.severity() as usize].load(Relaxed)
+            );
+        }
+    });
+}
@@ -1,4 +1,51 @@
 extern crate std;
 
+use std::string::String;
+
+mod assert_tests;
+mod deferred_tests;
+mod filter_tests;
+mod hexdump_tests;
+mod interned_tests;
+mod level_gate_tests;
+mod ring_buffer_tests;
+mod sink_tests;
+mod style_tests;
+mod timestamp_tests;
 mod trace_string_tests;
 mod trace_tests;
+
+/// Decode a frame traced by this binary, with the format string read from its `.trace_strings`
+/// section
+#[cfg(feature = "interned")]
+fn decode_traced(frame: &[u8]) -> (crate::Level, String) {
+    use crate::interned::{Decoder, RAW_INDEX};
+
+    let mut index = 0;
+    for (i, &byte) in frame[1..].iter().enumerate() {
+        index |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    let mut res = String::new();
+    let level = match index {
+        RAW_INDEX => Decoder::new(&[], 0).decode(frame, &mut res),
+        _ => {
+            // Interned strings are null terminated
+            let format = unsafe { core::ffi::CStr::from_ptr(index as *const core::ffi::c_char) };
+            Decoder::new(format.to_bytes_with_nul(), index).decode(frame, &mut res)
+        }
+    };
+    (level.unwrap(), res)
+}
+
+/// The text of the bytes of a record or ring buffer entry, with frames of the `interned` feature
+/// decoded the way the host would
+fn text(bytes: &[u8]) -> String {
+    #[cfg(not(feature = "interned"))]
+    return String::from(core::str::from_utf8(bytes).unwrap());
+    #[cfg(feature = "interned")]
+    return decode_traced(bytes).1;
+}
@@ -0,0 +1,117 @@
+use std::{string::String, vec::Vec};
+
+use crate::{Level, RingBuffer};
+
+fn messages<const N: usize, const LENGTH: usize>(
+    buffer: &RingBuffer<N, LENGTH>,
+) -> Vec<(Level, String)> {
+    let mut messages = Vec::new();
+    buffer.for_each(|entry| messages.push((entry.level(), String::from(entry.message()))));
+    messages
+}
+
+#[test]
+fn ring_buffer_keeps_most_recent() {
+    let buffer: RingBuffer<3> = RingBuffer::new();
+    assert!(buffer.is_empty());
+
+    buffer.push(Level::Debug, b"first");
+    buffer.push(Level::Info, b"second");
+    assert_eq!(
+        vec![
+            (Level::Debug, String::from("first")),
+            (Level::Info, String::from("second")),
+        ],
+        messages(&buffer)
+    );
+
+    buffer.push(Level::Warning, b"third");
+    buffer.push(Level::Error, b"fourth");
+    assert_eq!(3, buffer.len());
+    assert_eq!(
+        vec![
+            (Level::Info, String::from("second")),
+            (Level::Warning, String::from("third")),
+            (Level::Error, String::from("fourth")),
+        ],
+        messages(&buffer)
+    );
+}
+
+// Frames can't be cut in interned mode
+#[cfg(not(feature = "interned"))]
+#[test]
+fn ring_buffer_truncates_long_messages() {
+    critical_section::with(|_| {
+        let buffer: RingBuffer<2, 16> = RingBuffer::new();
+        let truncated = crate::truncated_messages();
+
+        buffer.push(Level::Info, b"fits in an entry");
+        // A multi byte character across the cut is left out, before the truncation marker
+        buffer.push(Level::Info, "x\u{e9}xxxxxxxxxxxxxxx".as_bytes());
+
+        assert_eq!(
+            vec![
+                (Level::Info, String::from("fits in an entry")),
+                (Level::Info, String::from("x…[truncated]")),
+            ],
+            messages(&buffer)
+        );
+        assert_eq!(truncated + 1, crate::truncated_messages());
+    });
+}
+
+#[cfg(feature = "interned")]
+#[test]
+fn ring_buffer_drops_long_frames() {
+    let buffer: RingBuffer<2, 4> = RingBuffer::new();
+
+    buffer.push(Level::Info, b"abcd");
+    buffer.push(Level::Info, b"abcdef");
+    assert_eq!(1, buffer.dropped());
+    assert_eq!(1, buffer.len());
+}
+
+#[test]
+fn ring_buffer_drain_and_clear() {
+    let buffer: RingBuffer<4> = RingBuffer::new();
+    buffer.push(Level::Info, b"first");
+    buffer.push(Level::Error, b"second");
+
+    let mut drained = Vec::new();
+    assert!(buffer.drain(|entry| drained.push(String::from(entry.message()))));
+    assert_eq!(vec![String::from("first"), String::from("second")], drained);
+    assert!(buffer.is_empty());
+
+    buffer.push(Level::Info, b"third");
+    assert_eq!(
+        vec![(Level::Info, String::from("third"))],
+        messages(&buffer)
+    );
+    assert!(buffer.clear());
+    assert!(messages(&buffer).is_empty());
+}
+
+#[test]
+fn ring_buffer_drops_while_busy() {
+    let buffer: RingBuffer<4> = RingBuffer::new();
+    buffer.push(Level::Info, b"first");
+
+    // Traces from within the callback act as an interrupting trace would
+    buffer.for_each(|_| buffer.push(Level::Info, b"nested"));
+    assert_eq!(1, buffer.dropped());
+    assert_eq!(1, buffer.len());
+}
+
+#[test]
+fn ring_buffer_len_while_busy() {
+    let buffer: RingBuffer<4> = RingBuffer::new();
+    buffer.push(Level::Info, b"first");
+    buffer.push(Level::Info, b"second");
+
+    // A reader interrupting a drain sees the records from before it started
+    let mut len = 0;
+    buffer.drain(|_| len = buffer.len());
+    assert_eq!(2, len);
+    assert_eq!(0, buffer.len());
+}
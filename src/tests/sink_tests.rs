@@ -0,0 +1,64 @@
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+    vec::Vec,
+};
+
+use crate::{Level, Record, Sink, SinkError, TraceHandler, add_sink, remove_sink};
+
+struct NullHandler;
+
+impl TraceHandler for NullHandler {
+    fn log(&self, _record: &Record) {}
+}
+
+#[test]
+fn concurrent_add_sink_registers_once() {
+    const THREADS: usize = 8;
+
+    critical_section::with(|_| {
+        static SINK: Sink = Sink::new(&NullHandler, Level::Trace);
+
+        for _ in 0..100 {
+            let barrier = Arc::new(Barrier::new(THREADS));
+            let adders: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        loop {
+                            match add_sink(&SINK) {
+                                Err(SinkError::Busy) => thread::yield_now(),
+                                res => return res,
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            let results: Vec<_> = adders
+                .into_iter()
+                .map(|adder| adder.join().unwrap())
+                .collect();
+            assert_eq!(1, results.iter().filter(|res| res.is_ok()).count());
+            assert!(
+                results
+                    .iter()
+                    .all(|res| matches!(res, Ok(()) | Err(SinkError::AlreadyAdded)))
+            );
+
+            // Removed exactly once, so it was only in one slot
+            assert!(remove_sink(&SINK));
+            assert!(!remove_sink(&SINK));
+        }
+    });
+}
+
+#[test]
+fn sink_level_round_trips() {
+    let sink = Sink::new(&NullHandler, Level::Trace);
+    for level in Level::ALL {
+        sink.set_level(level);
+        assert_eq!(level, sink.level());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{format, string::String};
+
+use crate::{Callsite, Color, Level, Part, Style, Styled, Timestamp, clear_style, set_style};
+
+static CALLSITE: Callsite = Callsite::new("net::tcp", "net::tcp", "src/net/tcp.rs", 42);
+const TIMESTAMP: Timestamp = Timestamp::from_ticks(12_345_678);
+
+fn styled(style: &'static Style, level: Level, message: &str) -> String {
+    critical_section::with(|_| {
+        set_style(style);
+        let styled = format!(
+            "{}",
+            Styled::new(level, TIMESTAMP, &CALLSITE, format_args!("{}", message))
+        );
+        clear_style();
+        styled
+    })
+}
+
+#[test]
+fn style_default() {
+    #[cfg(not(feature = "no-color"))]
+    let expected = "\x1b[33mWARNING: hello\x1b[0m\r\n";
+    #[cfg(feature = "no-color")]
+    let expected = "WARNING: hello\r\n";
+
+    assert_eq!(expected, styled(&Style::DEFAULT, Level::Warning, "hello"));
+}
+
+#[test]
+fn style_labels_and_parts() {
+    static STYLE: Style = Style {
+        labels: ["T", "D", "I", "W", "E", "F"],
+        colors: [Color::None; 6],
+        parts: &[
+            Part::Label,
+            Part::Text(" "),
+            Part::Target,
+            Part::Text(" "),
+            Part::Location,
+            Part::Text(" | "),
+            Part::Message,
+        ],
+        line_end: "\n",
+    };
+
+    assert_eq!(
+        "I net::tcp src/net/tcp.rs:42 | connected\n",
+        styled(&STYLE, Level::Info, "connected")
+    );
+    assert_eq!(
+        "E net::tcp src/net/tcp.rs:42 | reset\n",
+        styled(&STYLE, Level::Error, "reset")
+    );
+}
+
+#[test]
+fn style_colors() {
+    static STYLE: Style = Style {
+        colors: [
+            Color::Fixed(240),
+            Color::Fixed(244),
+            Color::None,
+            Color::Fixed(208),
+            Color::Blue,
+            Color::Red,
+        ],
+        ..Style::DEFAULT
+    };
+
+    assert_eq!(
+        "\x1b[38;5;244mDEBUG: a\x1b[0m\r\n",
+        styled(&STYLE, Level::Debug, "a")
+    );
+    assert_eq!("INFO: b\r\n", styled(&STYLE, Level::Info, "b"));
+    assert_eq!(
+        "\x1b[34mERROR: c\x1b[0m\r\n",
+        styled(&STYLE, Level::Error, "c")
+    );
+}
+
+#[test]
+fn style_timestamp() {
+    static STYLE: Style = Style {
+        colors: [Color::None; 6],
+        parts: &[Part::Timestamp, Part::Text(" "), Part::Message],
+        ..Style::DEFAULT
+    };
+
+    assert_eq!("[12345678] tick\r\n", styled(&STYLE, Level::Info, "tick"));
+}
+
+// Checks the formatted text, which is not produced in interned mode, and is only passed on when
+// drained in deferred mode
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[cfg(not(any(feature = "interned", feature = "deferred")))]
+#[test]
+fn style_applies_to_leveled_macros() {
+    use std::vec::Vec;
+
+    use crate::{RingBuffer, Sink, add_sink, remove_sink, trace, trace_info};
+
+    static STYLE: Style = Style {
+        labels: ["trc ", "dbg ", "inf ", "wrn ", "err ", "ftl "],
+        colors: [Color::None; 6],
+        ..Style::DEFAULT
+    };
+
+    critical_section::with(|_| {
+        static BUFFER: RingBuffer<4> = RingBuffer::new();
+        static SINK: Sink = Sink::new(&BUFFER, Level::Debug);
+        assert_eq!(Ok(()), add_sink(&SINK));
+        set_style(&STYLE);
+
+        trace_info!("styled");
+        // Only the leveled macros are styled
+        trace!("plain");
+
+        clear_style();
+        assert!(remove_sink(&SINK));
+
+        let mut messages = Vec::new();
+        BUFFER.drain(|entry| messages.push(String::from(entry.message())));
+        assert_eq!(
+            vec![String::from("inf styled\r\n"), String::from("plain")],
+            messages
+        );
+    });
+}
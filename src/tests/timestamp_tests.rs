@@ -0,0 +1,26 @@
+use std::format;
+
+use crate::Timestamp;
+
+#[test]
+fn timestamp_displays_ticks() {
+    assert_eq!(
+        "[12345678]",
+        format!("{}", Timestamp::from_ticks(12_345_678))
+    );
+}
+
+#[test]
+fn timestamp_displays_uptime() {
+    let timestamp = Timestamp::from_ticks(12_345_678);
+    assert_eq!("[  12.345678]", format!("{}", timestamp.uptime(1_000_000)));
+    assert_eq!("[1234.567800]", format!("{}", timestamp.uptime(10_000)));
+    assert_eq!(
+        "[   0.500000]",
+        format!("{}", Timestamp::from_ticks(16_384).uptime(32_768))
+    );
+    assert_eq!(
+        "[   1.000000]",
+        format!("{}", Timestamp::from_ticks(u64::MAX).uptime(u64::MAX))
+    );
+}
@@ -1,4 +1,11 @@
-use crate::{TraceString, format};
+use critical_section::with as critical;
+
+use crate::{
+    TRACE_FORMAT_BUFFER_SIZE, TraceString, format, set_truncation_marker, truncated_messages,
+    truncation_marker,
+};
+
+static DEFAULT_MARKER: &str = "…[truncated]";
 
 #[test]
 fn create_string() {
@@ -19,3 +26,62 @@ fn format_string() {
         assert_eq!(string.buffer[i], RES.as_bytes()[i]);
     }
 }
+
+#[test]
+fn truncate_at_char_boundary() {
+    critical(|_| {
+        let truncated = truncated_messages();
+        let long = "é".repeat(TRACE_FORMAT_BUFFER_SIZE);
+        let string = format(format_args!("{}", long));
+
+        let marker = truncation_marker();
+        assert_eq!("…[truncated]", marker);
+        let text = string.to_string();
+        assert!(text.ends_with(marker));
+        assert!(text.len() <= TRACE_FORMAT_BUFFER_SIZE);
+        // Only whole 2 byte characters are kept
+        assert_eq!(
+            (TRACE_FORMAT_BUFFER_SIZE - marker.len()) / 2,
+            text.trim_end_matches(marker).chars().count()
+        );
+        assert_eq!(truncated + 1, truncated_messages());
+    })
+}
+
+#[test]
+fn truncate_written_text_for_marker() {
+    critical(|_| {
+        static MARKER: &str = "[cut]";
+        set_truncation_marker(&MARKER);
+
+        let text = "x".repeat(TRACE_FORMAT_BUFFER_SIZE - 2);
+        let string = format(format_args!("{}{}{}", text, "abc", "def"));
+        set_truncation_marker(&DEFAULT_MARKER);
+
+        let expected = "x".repeat(TRACE_FORMAT_BUFFER_SIZE - MARKER.len()) + MARKER;
+        assert_eq!(expected, string.to_string());
+    })
+}
+
+#[test]
+fn message_that_fits_is_not_truncated() {
+    critical(|_| {
+        let text = "x".repeat(TRACE_FORMAT_BUFFER_SIZE);
+        let string = format(format_args!("{}", text));
+        assert_eq!(text, string.to_string());
+    })
+}
+
+// Text is formatted straight into the frame in interned mode
+#[cfg(not(feature = "interned"))]
+#[test]
+fn nested_formatting() {
+    use crate::with_formatted;
+
+    let nested = with_formatted(format_args!("outer"), |outer| {
+        assert_eq!("outer", outer);
+        with_formatted(format_args!("inner"), |inner| assert_eq!("inner", inner)).is_some()
+    });
+    // The static buffer is still in use by the outer message
+    assert_eq!(Some(!cfg!(feature = "static-buffer")), nested);
+}
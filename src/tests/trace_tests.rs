@@ -1,23 +1,58 @@
-use core::cell::RefCell;
+// Tests of leveled macros are only built if the lowest level they trace is compiled in, using the
+// cfgs of the level gates. Helpers of the skipped tests are left unused
+#![cfg_attr(
+    not(any(trace_release_level_trace, all(debug_assertions, trace_level_trace))),
+    allow(unused_imports, dead_code)
+)]
+
+use core::{
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+};
 use critical_section::{Mutex, with as critical};
 
 use crate::{
-    Level, TraceString, format, trace, trace_debug, trace_debug_once, trace_error,
-    trace_error_once, trace_handler, trace_info, trace_info_once, trace_once, trace_panic,
-    trace_warning, trace_warning_once, traceln, traceln_once,
+    Callsite, Filter, Level, Record, ReentrancyPolicy, RingBuffer, Sink, SinkError, Timestamp,
+    TraceHandler, TraceString, add_sink, clear_filter, clear_handler, flush, format, max_level,
+    nested_dropped, remove_sink, set_filter, set_handler, set_max_level, set_reentrancy_policy,
+    trace, trace_core_id, trace_debug, trace_debug_once, trace_error, trace_error_once,
+    trace_every_n, trace_fatal, trace_fatal_once, trace_first_n, trace_format, trace_handler,
+    trace_info, trace_info_once, trace_once, trace_panic, trace_throttle, trace_timestamp,
+    trace_trace, trace_trace_once, trace_warning, trace_warning_once, traceln, traceln_once,
 };
 
-static TEST_TRACE_HANDLER: TestTraceHandler = TestTraceHandler::new();
+// Tests of text that is only formatted on the target, and can't be decoded from interned frames
+#[cfg(not(feature = "interned"))]
+mod message_tests;
 
 #[trace_handler]
-fn on_trace(level: Level, msg: &str) {
-    TEST_TRACE_HANDLER.trace_write(level, msg);
+static TEST_TRACE_HANDLER: TestTraceHandler = TestTraceHandler::new();
+
+static TEST_TICKS: AtomicU64 = AtomicU64::new(0);
+
+#[trace_timestamp]
+fn test_timestamp() -> u64 {
+    TEST_TICKS.load(Relaxed)
+}
+
+std::thread_local! {
+    static TEST_CORE: Cell<u32> = const { Cell::new(0) };
+}
+
+#[trace_core_id]
+fn test_core_id() -> u32 {
+    TEST_CORE.with(Cell::get)
 }
 
 #[derive(Clone)]
 struct TraceResult {
     level: Level,
+    timestamp: Timestamp,
     msg: TraceString,
+    callsite: Callsite,
+    fields: TraceString,
+    min_level: Level,
+    flushes: usize,
 }
 
 type TraceBuffer = Mutex<RefCell<TraceResult>>;
@@ -30,7 +65,12 @@ impl TraceResult {
     const fn new() -> Self {
         Self {
             level: Level::Debug,
+            timestamp: Timestamp::from_ticks(0),
             msg: TraceString::new(),
+            callsite: Callsite::new("", "", "", 0),
+            fields: TraceString::new(),
+            min_level: Level::Trace,
+            flushes: 0,
         }
     }
 }
@@ -43,6 +83,7 @@ impl TestTraceHandler {
     }
 
     fn get_result(&self) -> TraceResult {
+        drain_deferred();
         let res = critical(|cs| self.buffer.borrow(cs).borrow().clone());
         std::println!(
             "get_result level: {:?}, msg: {}",
@@ -54,18 +95,67 @@ impl TestTraceHandler {
 
     fn reset(&self) {
         std::println!("reset");
+        // Records left over by an earlier test are dropped
+        #[cfg(feature = "deferred")]
+        crate::drain(|_| {});
         critical(|cs| *self.buffer.borrow(cs).borrow_mut() = TraceResult::new())
     }
 
-    fn trace_write(&self, level: Level, msg: &str) {
-        std::println!("Got msg {}", msg);
+    fn set_min_level(&self, level: Level) {
+        critical(|cs| self.buffer.borrow(cs).borrow_mut().min_level = level)
+    }
+}
+
+impl TraceHandler for TestTraceHandler {
+    fn log(&self, record: &Record) {
+        let text = super::text(record.bytes());
+        std::println!("Got msg {}", text);
 
-        let _ = critical(|cs| {
+        critical(|cs| {
             let mut current_ref = self.buffer.borrow(cs).borrow_mut();
-            current_ref.msg = format(format_args!("{}{}", current_ref.msg.to_string(), msg));
-            current_ref.level = level;
+            current_ref.msg = format(format_args!("{}{}", current_ref.msg.to_string(), text));
+            current_ref.level = record.level();
+            current_ref.timestamp = record.timestamp();
+            current_ref.callsite = *record.callsite();
+            for field in record.fields() {
+                current_ref.fields =
+                    format(format_args!("{}{} ", current_ref.fields.to_string(), field));
+            }
         });
     }
+
+    fn enabled(&self, level: Level) -> bool {
+        critical(|cs| level >= self.buffer.borrow(cs).borrow().min_level)
+    }
+
+    fn flush(&self) {
+        critical(|cs| self.buffer.borrow(cs).borrow_mut().flushes += 1)
+    }
+}
+
+struct CountingHandler {
+    count: AtomicUsize,
+}
+
+impl CountingHandler {
+    const fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl TraceHandler for CountingHandler {
+    fn log(&self, _record: &Record) {
+        self.count.fetch_add(1, Relaxed);
+    }
+}
+
+/// Pass on the records queued with the `deferred` feature, as the handlers only get them once the
+/// queue is drained
+fn drain_deferred() {
+    #[cfg(feature = "deferred")]
+    crate::drain_to_handlers();
 }
 
 struct TraceTestGuard {}
@@ -84,6 +174,12 @@ const STRING_NEWLINE: &str = if cfg!(feature = "no-color") {
     "\u{1b}[0mHello, World!\r\n"
 };
 
+const STRING_TRACE: &str = if cfg!(feature = "no-color") {
+    "TRACE: Hello, World!\r\n"
+} else {
+    "\u{1b}[34mTRACE: Hello, World!\u{1b}[0m\r\n"
+};
+
 const STRING_DEBUG: &str = if cfg!(feature = "no-color") {
     "DEBUG: Hello, World!\r\n"
 } else {
@@ -108,12 +204,19 @@ const STRING_ERROR: &str = if cfg!(feature = "no-color") {
     "\u{1b}[31mERROR: Hello, World!\u{1b}[0m\r\n"
 };
 
+const STRING_FATAL: &str = if cfg!(feature = "no-color") {
+    "FATAL: Hello, World!\r\n"
+} else {
+    "\u{1b}[31mFATAL: Hello, World!\u{1b}[0m\r\n"
+};
+
 const STRING_PANIC: &str = if cfg!(feature = "no-color") {
     "PANIC: Hello, World!\r\n"
 } else {
     "\u{1b}[31mPANIC: Hello, World!\u{1b}[0m\r\n"
 };
 
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
 #[test]
 fn trace_string() {
     critical(|_| {
@@ -127,6 +230,7 @@ fn trace_string() {
     })
 }
 
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
 #[test]
 fn trace_newline() {
     critical(|_| {
@@ -140,6 +244,21 @@ fn trace_newline() {
     })
 }
 
+#[cfg(any(trace_release_level_trace, all(debug_assertions, trace_level_trace)))]
+#[test]
+fn trace_trace() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        trace_trace!("{}", STRING);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Trace, res.level);
+        assert_eq!(STRING_TRACE, res.msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_debug, all(debug_assertions, trace_level_debug)))]
 #[test]
 fn trace_debug() {
     critical(|_| {
@@ -153,6 +272,7 @@ fn trace_debug() {
     })
 }
 
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
 #[test]
 fn trace_info() {
     critical(|_| {
@@ -166,6 +286,10 @@ fn trace_info() {
     })
 }
 
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
 #[test]
 fn trace_warning() {
     critical(|_| {
@@ -179,6 +303,7 @@ fn trace_warning() {
     })
 }
 
+#[cfg(any(trace_release_level_error, all(debug_assertions, trace_level_error)))]
 #[test]
 fn trace_error() {
     critical(|_| {
@@ -192,6 +317,21 @@ fn trace_error() {
     })
 }
 
+#[cfg(any(trace_release_level_fatal, all(debug_assertions, trace_level_fatal)))]
+#[test]
+fn trace_fatal() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        trace_fatal!("{}", STRING);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Fatal, res.level);
+        assert_eq!(STRING_FATAL, res.msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_fatal, all(debug_assertions, trace_level_fatal)))]
 #[test]
 fn trace_panic() {
     critical(|_| {
@@ -200,11 +340,684 @@ fn trace_panic() {
 
         trace_panic!("{}", STRING);
         let res = TEST_TRACE_HANDLER.get_result();
-        assert_eq!(Level::Error, res.level);
+        assert_eq!(Level::Fatal, res.level);
         assert_eq!(STRING_PANIC, res.msg.to_string());
     })
 }
 
+#[test]
+fn levels_are_ordered_by_severity() {
+    assert!(Level::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(Level::Trace < Level::Debug && Level::Error < Level::Fatal);
+
+    // The values are stable for C handlers and interned frames
+    for level in Level::ALL {
+        assert_eq!(Ok(level), Level::try_from(level as u8));
+    }
+    assert_eq!(0, Level::Debug as u8);
+    assert_eq!(3, Level::Error as u8);
+    assert_eq!(4, Level::Trace as u8);
+    assert_eq!(5, Level::Fatal as u8);
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn trace_callsite() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        let line = line!() + 1;
+        trace_info!("{}", STRING);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(module_path!(), res.callsite.module_path());
+        assert_eq!(file!(), res.callsite.file());
+        assert_eq!(line, res.callsite.line());
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn trace_target_filter() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        static FILTER: Filter = Filter::new("net=error,trace::tests=warning");
+        set_filter(&FILTER);
+        trace_info!("{}", STRING);
+        trace_warning!(target: "net", "{}", STRING);
+        let filtered = TEST_TRACE_HANDLER.get_result();
+        trace_warning!("{}", STRING);
+        clear_filter();
+
+        assert_eq!("", filtered.msg.to_string());
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(module_path!(), res.callsite.target());
+        assert_eq!(STRING_WARNING, res.msg.to_string());
+
+        TEST_TRACE_HANDLER.reset();
+        trace_error!(target: "net", "{}", STRING);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!("net", res.callsite.target());
+        assert_eq!(module_path!(), res.callsite.module_path());
+        assert_eq!(STRING_ERROR, res.msg.to_string());
+    })
+}
+
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
+#[test]
+fn handler_enabled_filters_before_formatting() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        fn formatted(count: &mut usize) -> &'static str {
+            *count += 1;
+            STRING
+        }
+
+        let mut count = 0;
+        TEST_TRACE_HANDLER.set_min_level(Level::Error);
+        trace_warning!("{}", formatted(&mut count));
+        assert_eq!(0, count);
+        assert_eq!("", TEST_TRACE_HANDLER.get_result().msg.to_string());
+
+        trace_error!("{}", formatted(&mut count));
+        assert_eq!(1, count);
+        assert_eq!(
+            STRING_ERROR,
+            TEST_TRACE_HANDLER.get_result().msg.to_string()
+        );
+    })
+}
+
+#[test]
+fn handler_flush() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        flush();
+        flush();
+        assert_eq!(2, TEST_TRACE_HANDLER.get_result().flushes);
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn runtime_handler() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        static COUNTING_HANDLER: CountingHandler = CountingHandler::new();
+        static HANDLER: &dyn TraceHandler = &COUNTING_HANDLER;
+
+        set_handler(&HANDLER);
+        trace_info!("{}", STRING);
+        trace_info!("{}", STRING);
+        drain_deferred();
+        clear_handler();
+        trace_info!("{}", STRING);
+
+        assert_eq!(2, COUNTING_HANDLER.count.load(Relaxed));
+        assert_eq!(STRING_INFO, TEST_TRACE_HANDLER.get_result().msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_debug, all(debug_assertions, trace_level_debug)))]
+#[test]
+fn sinks_with_levels() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        static ERROR_HANDLER: CountingHandler = CountingHandler::new();
+        static ALL_HANDLER: CountingHandler = CountingHandler::new();
+        static ERROR_SINK: Sink = Sink::new(&ERROR_HANDLER, Level::Error);
+        static ALL_SINK: Sink = Sink::new(&ALL_HANDLER, Level::Debug);
+
+        assert_eq!(Ok(()), add_sink(&ERROR_SINK));
+        assert_eq!(Ok(()), add_sink(&ALL_SINK));
+        assert_eq!(Err(SinkError::AlreadyAdded), add_sink(&ALL_SINK));
+
+        trace_debug!("{}", STRING);
+        trace_warning!("{}", STRING);
+        trace_error!("{}", STRING);
+        drain_deferred();
+
+        assert!(remove_sink(&ERROR_SINK));
+        assert!(remove_sink(&ALL_SINK));
+        assert!(!remove_sink(&ALL_SINK));
+        trace_error!("{}", STRING);
+
+        assert_eq!(1, ERROR_HANDLER.count.load(Relaxed));
+        assert_eq!(3, ALL_HANDLER.count.load(Relaxed));
+        assert_eq!(Level::Error, TEST_TRACE_HANDLER.get_result().level);
+    })
+}
+
+#[cfg(any(trace_release_level_debug, all(debug_assertions, trace_level_debug)))]
+#[test]
+fn ring_buffer_sink() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        static HISTORY: RingBuffer<2> = RingBuffer::new();
+        static HISTORY_SINK: Sink = Sink::new(&HISTORY, Level::Warning);
+
+        assert_eq!(Ok(()), add_sink(&HISTORY_SINK));
+        trace_debug!("{}", STRING);
+        trace_warning!("{}", STRING);
+        trace_error!("{}", STRING);
+        drain_deferred();
+        assert!(remove_sink(&HISTORY_SINK));
+
+        let mut levels = [Level::Debug; 2];
+        let mut count = 0;
+        HISTORY.drain(|entry| {
+            levels[count] = entry.level();
+            count += 1;
+        });
+        assert_eq!(2, count);
+        assert_eq!([Level::Warning, Level::Error], levels);
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn trace_fields() {
+    use crate::Value;
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        #[derive(Debug)]
+        enum State {
+            Running,
+        }
+
+        let temp = 21.5f32;
+        trace_info!(target: "motor", temp = temp, rpm = 1200u32, name = "left", state = Value::Debug(&State::Running); "{}", STRING);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!("motor", res.callsite.target());
+        assert_eq!(STRING_INFO, res.msg.to_string());
+        // Fields are not kept by the deferred queue
+        let fields = |fields| {
+            if cfg!(feature = "deferred") {
+                ""
+            } else {
+                fields
+            }
+        };
+        assert_eq!(
+            fields("temp=21.5 rpm=1200 name=left state=Running "),
+            res.fields.to_string()
+        );
+
+        TEST_TRACE_HANDLER.reset();
+        trace!(offset = -3; "{}", STRING);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(STRING, res.msg.to_string());
+        assert_eq!(fields("offset=-3 "), res.fields.to_string());
+    })
+}
+
+#[test]
+fn trace_format_callsite() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        let line = line!() + 1;
+        trace_format(Level::Info, format_args!("{}", STRING));
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!("", res.callsite.module_path());
+        assert_eq!(file!(), res.callsite.file());
+        assert_eq!(line, res.callsite.line());
+    })
+}
+
+#[cfg(any(trace_release_level_trace, all(debug_assertions, trace_level_trace)))]
+#[cfg(feature = "log")]
+#[test]
+fn log_bridge() {
+    use log::Log;
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        let log = |level: log::Level, target: &str| {
+            crate::log_bridge::LOGGER.log(
+                &log::Record::builder()
+                    .level(level)
+                    .target(target)
+                    .module_path_static(Some("driver"))
+                    .args(format_args!("{}", STRING))
+                    .build(),
+            )
+        };
+
+        log(log::Level::Warn, "driver");
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Warning, res.level);
+        assert_eq!(STRING_WARNING, res.msg.to_string());
+        assert_eq!("driver", res.callsite.target());
+
+        TEST_TRACE_HANDLER.reset();
+        static FILTER: Filter = Filter::new("driver=error");
+        set_filter(&FILTER);
+        log(log::Level::Info, "driver");
+        log(log::Level::Trace, "other");
+        clear_filter();
+
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Trace, res.level);
+        assert_eq!(STRING_TRACE, res.msg.to_string());
+        assert_eq!("", res.callsite.target());
+    })
+}
+
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_layer() {
+    use tracing_subscriber::prelude::*;
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        let subscriber = tracing_subscriber::registry().with(crate::tracing_bridge::TraceLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(rpm = 1200u64, state = "idle", "{}", STRING)
+        });
+
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Warning, res.level);
+        assert_eq!(STRING_WARNING, res.msg.to_string());
+        // Fields are not kept by the deferred queue
+        if !cfg!(feature = "deferred") {
+            assert_eq!("rpm=1200 state=idle ", res.fields.to_string());
+        }
+        assert_eq!(module_path!(), res.callsite.target());
+    })
+}
+
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
+// `TracingHandler` re-emits formatted messages, which interned mode doesn't have
+#[cfg(not(feature = "interned"))]
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_handler() {
+    use std::{format, string::String, vec::Vec};
+    use tracing::field::{Field, Visit};
+    use tracing_subscriber::{layer::Context, prelude::*};
+
+    static EVENTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    struct Capture;
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Capture {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            struct Visitor(String);
+            impl Visit for Visitor {
+                fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+                    self.0 += &format!(" {}={:?}", field.name(), value);
+                }
+            }
+
+            let mut visitor = Visitor(format!("{}", event.metadata().level()));
+            event.record(&mut visitor);
+            EVENTS.lock().unwrap().push(visitor.0);
+        }
+    }
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        static TRACING_HANDLER: &dyn TraceHandler = &crate::tracing_bridge::TracingHandler;
+        set_handler(&TRACING_HANDLER);
+        let subscriber = tracing_subscriber::registry().with(Capture);
+        tracing::subscriber::with_default(subscriber, || {
+            trace_warning!(target: "motor", rpm = 1200u32; "{}", STRING);
+            drain_deferred();
+        });
+        clear_handler();
+
+        let events = EVENTS.lock().unwrap();
+        assert_eq!(1, events.len());
+        assert!(events[0].starts_with(&format!(
+            "WARN message=WARNING: {} trace_target=\"motor\" file=\"{}\"",
+            STRING,
+            file!()
+        )));
+        // Fields are not kept by the deferred queue
+        if !cfg!(feature = "deferred") {
+            assert!(events[0].ends_with(" fields=rpm=1200"));
+        }
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn record_timestamp() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        TEST_TICKS.store(12_345_678, Relaxed);
+        trace_info!("{}", STRING);
+        TEST_TICKS.store(0, Relaxed);
+
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Timestamp::from_ticks(12_345_678), res.timestamp);
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn trace_every_n_and_first_n() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        for i in 0..7 {
+            trace_every_n!(3, trace!("{}", i));
+        }
+        assert_eq!("036", TEST_TRACE_HANDLER.get_result().msg.to_string());
+
+        TEST_TRACE_HANDLER.reset();
+        for i in 0..7 {
+            trace_first_n!(2, trace!("{}", i));
+        }
+        assert_eq!("01", TEST_TRACE_HANDLER.get_result().msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn trace_throttle() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        for now in [5, 8, 14, 15, 20, 40] {
+            trace_throttle!(10, now: now, trace!("{},", now));
+        }
+        assert_eq!("5,15,40,", TEST_TRACE_HANDLER.get_result().msg.to_string());
+
+        TEST_TRACE_HANDLER.reset();
+        for ticks in [100, 150, 200] {
+            TEST_TICKS.store(ticks, Relaxed);
+            trace_throttle!(100, trace!("{},", ticks));
+        }
+        TEST_TICKS.store(0, Relaxed);
+        assert_eq!("100,200,", TEST_TRACE_HANDLER.get_result().msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn nested_traces() {
+    struct NestingHandler;
+
+    impl TraceHandler for NestingHandler {
+        fn log(&self, record: &Record) {
+            TEST_TRACE_HANDLER.log(record);
+            if super::text(record.bytes()) == "outer" {
+                trace!("inner");
+                trace!("inner");
+            }
+        }
+    }
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        static NESTING_HANDLER: &dyn TraceHandler = &NestingHandler;
+        set_handler(&NESTING_HANDLER);
+
+        let expected = |policy| match policy {
+            // The deferred queue takes nested traces like any other, and passes them on with the
+            // next drain
+            _ if cfg!(feature = "deferred") => "outerinnerinner",
+            // The static buffer is in use by the outer trace, so nothing can be queued. Interned
+            // mode formats into the frame instead
+            ReentrancyPolicy::Queue
+                if !cfg!(all(feature = "static-buffer", not(feature = "interned"))) =>
+            {
+                "outerinnerinner"
+            }
+            ReentrancyPolicy::Marker => {
+                if cfg!(feature = "no-color") {
+                    "outerWARNING: [2 nested traces dropped]\r\n"
+                } else {
+                    "outer\x1b[33mWARNING: [2 nested traces dropped]\x1b[0m\r\n"
+                }
+            }
+            _ => "outer",
+        };
+
+        for policy in [
+            ReentrancyPolicy::Drop,
+            ReentrancyPolicy::Marker,
+            ReentrancyPolicy::Queue,
+        ] {
+            TEST_TRACE_HANDLER.reset();
+            set_reentrancy_policy(policy);
+            let dropped = nested_dropped();
+
+            trace!("outer");
+            drain_deferred();
+
+            assert_eq!(
+                expected(policy),
+                TEST_TRACE_HANDLER.get_result().msg.to_string()
+            );
+            let queued = expected(policy).ends_with("inner");
+            assert_eq!(dropped + if queued { 0 } else { 2 }, nested_dropped());
+        }
+
+        set_reentrancy_policy(ReentrancyPolicy::Drop);
+        clear_handler();
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn nested_traces_on_other_cores() {
+    struct CoreHandler;
+
+    impl TraceHandler for CoreHandler {
+        fn log(&self, record: &Record) {
+            TEST_TRACE_HANDLER.log(record);
+            if super::text(record.bytes()) == "core 0" {
+                // Another core traces while core 0 is handling a trace
+                TEST_CORE.with(|core| core.set(1));
+                trace!("core 1");
+                TEST_CORE.with(|core| core.set(0));
+            }
+        }
+    }
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+        static CORE_HANDLER: &dyn TraceHandler = &CoreHandler;
+        set_handler(&CORE_HANDLER);
+        let dropped = nested_dropped();
+
+        trace!("core 0");
+        drain_deferred();
+
+        // Each core has a guard and a static format buffer of its own
+        assert_eq!(
+            "core 0core 1",
+            TEST_TRACE_HANDLER.get_result().msg.to_string()
+        );
+        assert_eq!(dropped, nested_dropped());
+        clear_handler();
+    })
+}
+
+// Nested traces are queued by the deferred queue instead of being dropped
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[cfg(not(feature = "deferred"))]
+#[test]
+fn nested_marker_is_cut_at_char_boundary() {
+    use crate::{Color, Part, Style, clear_style, set_style};
+
+    struct NestingHandler;
+
+    impl TraceHandler for NestingHandler {
+        fn log(&self, record: &Record) {
+            // Only the marker is kept, so the result fits in the smallest format buffer
+            match super::text(record.bytes()).as_str() {
+                "outer" => trace_info!("inner"),
+                _ => TEST_TRACE_HANDLER.log(record),
+            }
+        }
+    }
+
+    // Puts the two bytes of the label across the end of the 128 byte marker
+    const PADDING: &str = match core::str::from_utf8(&[b'-'; 127]) {
+        Ok(padding) => padding,
+        Err(_) => panic!(),
+    };
+    static STYLE: Style = Style {
+        labels: ["ä"; 6],
+        colors: [Color::None; 6],
+        parts: &[Part::Text(PADDING), Part::Label, Part::Message],
+        ..Style::DEFAULT
+    };
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+        static NESTING_HANDLER: &dyn TraceHandler = &NestingHandler;
+        set_handler(&NESTING_HANDLER);
+        set_reentrancy_policy(ReentrancyPolicy::Marker);
+        set_style(&STYLE);
+
+        trace!("outer");
+
+        clear_style();
+        set_reentrancy_policy(ReentrancyPolicy::Drop);
+        clear_handler();
+        let result = TEST_TRACE_HANDLER.get_result();
+        let marker = result.msg.to_string();
+        if cfg!(feature = "interned") {
+            // The frame of the marker can be smaller than the marker, and cut the padding as well
+            assert!(!marker.is_empty() && PADDING.starts_with(marker));
+        } else {
+            assert_eq!(PADDING, marker);
+        }
+    })
+}
+
+/// Events of a panic, in the order they happened
+static PANIC_EVENTS: std::sync::Mutex<std::vec::Vec<std::string::String>> =
+    std::sync::Mutex::new(std::vec::Vec::new());
+
+fn panic_event(event: &str) {
+    PANIC_EVENTS.lock().unwrap().push(event.into());
+}
+
+#[test]
+fn panic_is_flushed_before_halting() {
+    use std::{format, string::ToString, vec};
+
+    struct OrderHandler;
+
+    impl TraceHandler for OrderHandler {
+        fn log(&self, record: &Record) {
+            panic_event(super::text(record.bytes()).as_str());
+        }
+
+        fn flush(&self) {
+            panic_event("flush");
+        }
+    }
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        PANIC_EVENTS.lock().unwrap().clear();
+        static ORDER_HANDLER: &dyn TraceHandler = &OrderHandler;
+        set_handler(&ORDER_HANDLER);
+
+        let location = core::panic::Location::caller();
+        crate::panic::handle(Some(location), "boom", || panic_event("halt"));
+        clear_handler();
+
+        let report = format!(
+            "PANIC: {}:{}:{}: boom",
+            location.file(),
+            location.line(),
+            location.column()
+        );
+        let events = PANIC_EVENTS.lock().unwrap().clone();
+        assert_eq!(3, events.len());
+        assert!(events[0].contains(&report), "{}", events[0]);
+        assert_eq!(vec!["flush".to_string(), "halt".to_string()], events[1..]);
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn panic_in_handler_goes_to_handler_entry_point() {
+    use std::{string::ToString, vec};
+
+    /// Panics while handling a trace
+    struct PanickingHandler;
+
+    impl TraceHandler for PanickingHandler {
+        fn log(&self, record: &Record) {
+            panic_event(super::text(record.bytes()).as_str());
+            crate::panic::handle(None, "in handler", || panic_event("halt"));
+        }
+
+        fn flush(&self) {
+            panic_event("flush");
+        }
+    }
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+        PANIC_EVENTS.lock().unwrap().clear();
+        static PANICKING_HANDLER: &dyn TraceHandler = &PanickingHandler;
+        set_handler(&PANICKING_HANDLER);
+
+        trace!("outer");
+        drain_deferred();
+        clear_handler();
+
+        // The report skips the runtime handler, which is still handling the outer trace
+        assert_eq!(
+            vec!["outer".to_string(), "halt".to_string()],
+            PANIC_EVENTS.lock().unwrap().clone()
+        );
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Fatal, res.level);
+        assert!(res.msg.to_string().contains("PANIC: in handler"));
+        assert_eq!(1, res.flushes);
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
 #[test]
 fn trace_once_only_traces_once() {
     critical(|_| {
@@ -222,6 +1035,7 @@ fn trace_once_only_traces_once() {
     })
 }
 
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
 #[test]
 fn trace_ln_only_traces_once() {
     critical(|_| {
@@ -239,6 +1053,25 @@ fn trace_ln_only_traces_once() {
     })
 }
 
+#[cfg(any(trace_release_level_trace, all(debug_assertions, trace_level_trace)))]
+#[test]
+fn trace_trace_only_traces_once() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        fn trace_trace() {
+            trace_trace_once!("{}", STRING);
+        }
+        trace_trace();
+        trace_trace();
+
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(STRING_TRACE, res.msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_debug, all(debug_assertions, trace_level_debug)))]
 #[test]
 fn trace_debug_only_traces_once() {
     critical(|_| {
@@ -256,6 +1089,7 @@ fn trace_debug_only_traces_once() {
     })
 }
 
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
 #[test]
 fn trace_info_only_traces_once() {
     critical(|_| {
@@ -273,6 +1107,10 @@ fn trace_info_only_traces_once() {
     })
 }
 
+#[cfg(any(
+    trace_release_level_warning,
+    all(debug_assertions, trace_level_warning)
+))]
 #[test]
 fn trace_warning_once_only_traces_once() {
     critical(|_| {
@@ -290,6 +1128,7 @@ fn trace_warning_once_only_traces_once() {
     })
 }
 
+#[cfg(any(trace_release_level_error, all(debug_assertions, trace_level_error)))]
 #[test]
 fn trace_error_only_traces_once() {
     critical(|_| {
@@ -306,3 +1145,47 @@ fn trace_error_only_traces_once() {
         assert_eq!(STRING_ERROR, res.msg.to_string());
     })
 }
+
+#[cfg(any(trace_release_level_fatal, all(debug_assertions, trace_level_fatal)))]
+#[test]
+fn trace_fatal_only_traces_once() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        fn trace_fatal() {
+            trace_fatal_once!("{}", STRING);
+        }
+        trace_fatal();
+        trace_fatal();
+
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(STRING_FATAL, res.msg.to_string());
+    })
+}
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn max_level_filters_before_formatting() {
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        fn formatted(count: &mut usize) -> &'static str {
+            *count += 1;
+            STRING
+        }
+
+        let mut count = 0;
+        set_max_level(Level::Warning);
+        assert_eq!(Level::Warning, max_level());
+        trace_info!("{}", formatted(&mut count));
+        trace_warning!("{}", formatted(&mut count));
+        set_max_level(Level::Trace);
+
+        assert_eq!(1, count);
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Level::Warning, res.level);
+        assert_eq!(STRING_WARNING, res.msg.to_string());
+    })
+}
@@ -0,0 +1,112 @@
+use core::sync::atomic::Ordering::Relaxed;
+
+use critical_section::with as critical;
+
+use super::{TEST_TICKS, TEST_TRACE_HANDLER, TraceTestGuard};
+use crate::{Color, Part, Style, Timestamp, clear_style, set_style, trace_info};
+
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[test]
+fn styled_timestamp_is_record_timestamp() {
+    use std::format;
+
+    /// Moves the clock on while the message is formatted
+    struct Tick;
+
+    impl core::fmt::Display for Tick {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            TEST_TICKS.store(2, Relaxed);
+            f.write_str("tick")
+        }
+    }
+
+    static STYLE: Style = Style {
+        colors: [Color::None; 6],
+        parts: &[Part::Timestamp, Part::Text(" "), Part::Message],
+        ..Style::DEFAULT
+    };
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+        set_style(&STYLE);
+
+        TEST_TICKS.store(1, Relaxed);
+        trace_info!("{}", Tick);
+        TEST_TICKS.store(0, Relaxed);
+        clear_style();
+
+        let res = TEST_TRACE_HANDLER.get_result();
+        assert_eq!(Timestamp::from_ticks(1), res.timestamp);
+        assert_eq!(format!("{} tick\r\n", res.timestamp), res.msg.to_string());
+    })
+}
+
+// Deferred records are cut to the length of a queue entry before they are streamed
+#[cfg(any(trace_release_level_info, all(debug_assertions, trace_level_info)))]
+#[cfg(not(feature = "deferred"))]
+#[test]
+fn stream_handler() {
+    use core::cell::RefCell;
+    use std::string::String;
+
+    use critical_section::Mutex;
+
+    use super::{CountingHandler, STRING, STRING_INFO};
+    use crate::{
+        Callsite, Field, Level, Sink, StreamHandler, TRACE_FORMAT_BUFFER_SIZE, add_sink,
+        clear_stream_handler, remove_sink, set_stream_handler,
+    };
+
+    struct Stream {
+        // Messages, chunks and the streamed text
+        state: Mutex<RefCell<(usize, usize, String)>>,
+    }
+
+    impl StreamHandler for Stream {
+        fn begin(&self, level: Level, _callsite: &Callsite, fields: &[Field]) {
+            assert_eq!(Level::Info, level);
+            assert_eq!(1, fields.len());
+            critical(|cs| self.state.borrow(cs).borrow_mut().0 += 1);
+        }
+
+        fn write(&self, chunk: &str) {
+            critical(|cs| {
+                let mut state = self.state.borrow(cs).borrow_mut();
+                state.1 += 1;
+                state.2.push_str(chunk);
+            });
+        }
+
+        fn end(&self) {}
+    }
+
+    critical(|_| {
+        let _ = TraceTestGuard {};
+        TEST_TRACE_HANDLER.reset();
+
+        static STREAM: Stream = Stream {
+            state: Mutex::new(RefCell::new((0, 0, String::new()))),
+        };
+        static STREAM_HANDLER: &dyn StreamHandler = &STREAM;
+        static ALL_HANDLER: CountingHandler = CountingHandler::new();
+        static ALL_SINK: Sink = Sink::new(&ALL_HANDLER, Level::Debug);
+
+        set_stream_handler(&STREAM_HANDLER);
+        assert_eq!(Ok(()), add_sink(&ALL_SINK));
+        let long = "x".repeat(2 * TRACE_FORMAT_BUFFER_SIZE);
+        trace_info!(id = 1; "{}{}", STRING, long);
+        assert!(remove_sink(&ALL_SINK));
+        clear_stream_handler();
+
+        let (messages, chunks, text) = critical(|cs| STREAM.state.borrow(cs).borrow().clone());
+        assert_eq!(1, messages);
+        assert!(chunks > 1);
+        assert_eq!(
+            STRING_INFO.replace(STRING, &(String::from(STRING) + &long)),
+            text
+        );
+        assert_eq!(1, ALL_HANDLER.count.load(Relaxed));
+        assert_eq!("", TEST_TRACE_HANDLER.get_result().msg.to_string());
+    })
+}
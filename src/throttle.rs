@@ -0,0 +1,55 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering::Acquire, Ordering::Relaxed, Ordering::Release},
+};
+
+/// Time window limiting how often something happens, as used by
+/// [`trace_throttle!`](crate::trace_throttle). Only needs atomic booleans, as 64 bit atomics are
+/// missing on most microcontrollers
+pub struct Throttle {
+    locked: AtomicBool,
+    last: UnsafeCell<Option<u64>>,
+}
+
+// The last tick is only accessed while holding the lock
+unsafe impl Sync for Throttle {}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Throttle {
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            last: UnsafeCell::new(None),
+        }
+    }
+
+    /// Returns true on the first call, and then once at least `interval` ticks have passed since the
+    /// last call that returned true. Returns false if called again while another call is checking,
+    /// such as from an interrupt
+    pub fn ready(&self, now: u64, interval: u64) -> bool {
+        if self
+            .locked
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+
+        let last = unsafe { &mut *self.last.get() };
+        let ready = match *last {
+            Some(last) => now.wrapping_sub(last) >= interval,
+            None => true,
+        };
+        if ready {
+            *last = Some(now);
+        }
+
+        self.locked.store(false, Release);
+        ready
+    }
+}
@@ -0,0 +1,65 @@
+use core::fmt;
+
+// Defined by `#[trace_timestamp]`. The weak default always returns 0
+unsafe extern "C" {
+    fn _on_trace_timestamp() -> u64;
+}
+
+/// Time of a trace in ticks of the timestamp source registered with `#[trace_timestamp]`:
+///
+/// ```ignore
+/// #[trace_timestamp]
+/// fn now() -> u64 {
+///     TIMER.ticks()
+/// }
+/// ```
+///
+/// Displayed as raw ticks, such as `[12345678]`, or as uptime with [`Timestamp::uptime`]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Default)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    /// Read the registered timestamp source
+    pub fn now() -> Self {
+        Self(unsafe { _on_trace_timestamp() })
+    }
+
+    pub const fn ticks(&self) -> u64 {
+        self.0
+    }
+
+    /// Display the timestamp as seconds with microsecond precision, such as `[  12.345678]`
+    pub const fn uptime(&self, ticks_per_second: u64) -> Uptime {
+        Uptime {
+            ticks: self.0,
+            ticks_per_second,
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.0)
+    }
+}
+
+/// A timestamp displayed as uptime. Created with [`Timestamp::uptime`]
+#[derive(Clone, Copy, Debug)]
+pub struct Uptime {
+    ticks: u64,
+    ticks_per_second: u64,
+}
+
+impl fmt::Display for Uptime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ticks_per_second = u64::max(self.ticks_per_second, 1);
+        let seconds = self.ticks / ticks_per_second;
+        // Widened, as the remainder times a million can overflow for fast clocks
+        let micros = (self.ticks % ticks_per_second) as u128 * 1_000_000 / ticks_per_second as u128;
+        write!(f, "[{:>4}.{:06}]", seconds, micros)
+    }
+}
@@ -0,0 +1,231 @@
+//! Bridge to the `tracing` crate, provided by the `tracing` feature. [`TraceLayer`] passes `tracing`
+//! events on to the trace handler, and [`TracingHandler`] re-emits trace records as `tracing`
+//! events. Only one of them should be used at a time, as they would otherwise pass records back
+//! and forth
+
+extern crate std;
+
+use core::fmt::{self, Write};
+use std::{string::String, vec::Vec};
+
+use tracing::{Event, Metadata, Subscriber, field};
+use tracing_subscriber::{layer::Context, prelude::*, util::TryInitError};
+
+use crate::{
+    Callsite, Field, Level, Styled, Timestamp, Value, handler_enabled, target_enabled,
+    trace_format_at,
+};
+
+/// Register a `tracing` subscriber passing every event on to the trace handler
+pub fn init() -> Result<(), TryInitError> {
+    tracing_subscriber::registry().with(TraceLayer).try_init()
+}
+
+impl From<tracing::Level> for Level {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR => Level::Error,
+            tracing::Level::WARN => Level::Warning,
+            tracing::Level::INFO => Level::Info,
+            tracing::Level::DEBUG => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+/// Layer passing `tracing` events on to the trace handler. The `message` field is formatted like
+/// the trace macro of the same level, and other fields are passed on as record fields
+pub struct TraceLayer;
+
+impl<S: Subscriber> tracing_subscriber::Layer<S> for TraceLayer {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        // `tracing` macros are not removed by the level gates, so they are checked here
+        let level = Level::from(*metadata.level());
+        crate::__trace_level_compiled_in(level)
+            && target_enabled(level, metadata.target())
+            && handler_enabled(level)
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = Level::from(*metadata.level());
+        let callsite = Callsite::new(
+            metadata.target(),
+            metadata.module_path().unwrap_or(""),
+            metadata.file().unwrap_or(""),
+            metadata.line().unwrap_or(0),
+        );
+
+        let mut visitor = Visitor::default();
+        event.record(&mut visitor);
+        let fields: Vec<Field> = visitor
+            .fields
+            .iter()
+            .map(|(key, value)| Field::new(key, value.as_value()))
+            .collect();
+
+        let timestamp = Timestamp::now();
+        trace_format_at(
+            &callsite,
+            level,
+            timestamp,
+            &fields,
+            format_args!(
+                "{}",
+                Styled::new(
+                    level,
+                    timestamp,
+                    &callsite,
+                    format_args!("{}", visitor.message)
+                )
+            ),
+        );
+    }
+}
+
+/// A field value copied out of an event, as the values are only borrowed while visiting them
+enum OwnedValue {
+    Unsigned(u64),
+    Signed(i64),
+    F64(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl OwnedValue {
+    fn as_value(&self) -> Value<'_> {
+        match self {
+            OwnedValue::Unsigned(value) => Value::Unsigned(*value),
+            OwnedValue::Signed(value) => Value::Signed(*value),
+            OwnedValue::F64(value) => Value::F64(*value),
+            OwnedValue::Bool(value) => Value::Bool(*value),
+            OwnedValue::Str(value) => Value::Str(value),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Visitor {
+    message: String,
+    fields: Vec<(&'static str, OwnedValue)>,
+}
+
+impl field::Visit for Visitor {
+    fn record_u64(&mut self, field: &field::Field, value: u64) {
+        self.fields
+            .push((field.name(), OwnedValue::Unsigned(value)));
+    }
+
+    fn record_i64(&mut self, field: &field::Field, value: i64) {
+        self.fields.push((field.name(), OwnedValue::Signed(value)));
+    }
+
+    fn record_f64(&mut self, field: &field::Field, value: f64) {
+        self.fields.push((field.name(), OwnedValue::F64(value)));
+    }
+
+    fn record_bool(&mut self, field: &field::Field, value: bool) {
+        self.fields.push((field.name(), OwnedValue::Bool(value)));
+    }
+
+    fn record_str(&mut self, field: &field::Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.fields
+                .push((field.name(), OwnedValue::Str(String::from(value))));
+        }
+    }
+
+    fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let mut string = String::new();
+            let _ = write!(string, "{:?}", value);
+            self.fields.push((field.name(), OwnedValue::Str(string)));
+        }
+    }
+}
+
+/// Trace handler re-emitting records as `tracing` events with the target `trace`. The target,
+/// file and line of the trace are added as fields, and the message is emitted without colors or
+/// line endings
+///
+/// ```ignore
+/// static TRACING_HANDLER: &dyn TraceHandler = &TracingHandler;
+/// trace::set_handler(&TRACING_HANDLER);
+/// ```
+#[cfg(not(feature = "interned"))]
+pub struct TracingHandler;
+
+#[cfg(not(feature = "interned"))]
+impl crate::TraceHandler for TracingHandler {
+    fn log(&self, record: &crate::Record) {
+        // `tracing` levels have to be known at compile time
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    target: "trace",
+                    $level,
+                    trace_target = record.callsite().target(),
+                    file = record.callsite().file(),
+                    line = record.callsite().line(),
+                    fields = %Fields(record.fields()),
+                    "{}",
+                    Plain(record.message()),
+                )
+            };
+        }
+
+        // `tracing` has no level above error
+        match record.level() {
+            Level::Trace => emit!(tracing::Level::TRACE),
+            Level::Debug => emit!(tracing::Level::DEBUG),
+            Level::Info => emit!(tracing::Level::INFO),
+            Level::Warning => emit!(tracing::Level::WARN),
+            Level::Error | Level::Fatal => emit!(tracing::Level::ERROR),
+        }
+    }
+}
+
+/// Fields displayed as `key=value`, separated by spaces
+#[cfg(not(feature = "interned"))]
+struct Fields<'a>(&'a [Field<'a>]);
+
+#[cfg(not(feature = "interned"))]
+impl fmt::Display for Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(' ')?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+/// A message displayed without ANSI escape sequences and trailing line endings
+#[cfg(not(feature = "interned"))]
+struct Plain<'a>(&'a str);
+
+#[cfg(not(feature = "interned"))]
+impl fmt::Display for Plain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut chars = self.0.trim_end_matches(['\r', '\n']).chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // Skip the sequence up to and including its final byte
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) && c != '[' {
+                        break;
+                    }
+                }
+            } else {
+                f.write_char(c)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,35 @@
+#include <stdbool.h>
 #include <stddef.h>
+#include <stdint.h>
 
 // Use GCC/Clang weak attribute to circumvent not having weak rust linking in
 // stable. This should be updated when that is a stable feature, as this is a
 // hack
 __attribute__((weak)) void _on_trace(unsigned int, const unsigned char*, size_t) {}
+
+// Handlers that don't take a record only define _on_trace, so forward
+// to that by default
+__attribute__((weak)) void _on_trace_record(unsigned int level, const unsigned char* msg,
+                                            size_t msg_len, const void* record) {
+    (void)record;
+    _on_trace(level, msg, msg_len);
+}
+
+// Only defined by trace handler statics
+__attribute__((weak)) bool _on_trace_enabled(unsigned int) { return true; }
+__attribute__((weak)) void _on_trace_flush(void) {}
+
+// Defined by #[trace_timestamp]. Without a timestamp source, every record has
+// the timestamp 0
+__attribute__((weak)) uint64_t _on_trace_timestamp(void) { return 0; }
+
+// Defined by #[trace_core_id]. Without a core ID source, every trace is made
+// on core 0
+__attribute__((weak)) uint32_t _on_trace_core_id(void) { return 0; }
+
+// Called by the panic handler of the panic-handler feature after flushing
+__attribute__((weak, noreturn)) void _on_trace_panic(const void* info) {
+    (void)info;
+    for (;;) {
+    }
+}
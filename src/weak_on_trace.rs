@@ -1,8 +1,9 @@
 #[cfg(target_arch = "arm")]
 use core::arch::global_asm;
 
-// Create a weak symbol for _on_trace for ARM compilers. This should be updated to a #[weak]
-// attribute when that is part of the stable release
+// Create weak symbols for the trace handler entry points for ARM compilers. This should be updated
+// to a #[weak] attribute when that is part of the stable release. _on_trace_record shares its
+// first three arguments with _on_trace, so it can branch to it directly
 #[cfg(target_arch = "arm")]
 global_asm!(
     r#"
@@ -11,5 +12,41 @@ global_asm!(
     .type _on_trace, %function
 _on_trace:
     bx lr
+
+    .weak _on_trace_record
+    .type _on_trace_record, %function
+_on_trace_record:
+    ldr r3, =_on_trace
+    bx r3
+    .ltorg
+
+    .weak _on_trace_enabled
+    .type _on_trace_enabled, %function
+_on_trace_enabled:
+    movs r0, #1
+    bx lr
+
+    .weak _on_trace_flush
+    .type _on_trace_flush, %function
+_on_trace_flush:
+    bx lr
+
+    .weak _on_trace_timestamp
+    .type _on_trace_timestamp, %function
+_on_trace_timestamp:
+    movs r0, #0
+    movs r1, #0
+    bx lr
+
+    .weak _on_trace_core_id
+    .type _on_trace_core_id, %function
+_on_trace_core_id:
+    movs r0, #0
+    bx lr
+
+    .weak _on_trace_panic
+    .type _on_trace_panic, %function
+_on_trace_panic:
+    b _on_trace_panic
 "#
 );
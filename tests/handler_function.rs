@@ -0,0 +1,39 @@
+//! The `(level, msg)` form of a `#[trace_handler]` function, which is called through the
+//! `_on_trace` entry point. Each form exports its own entry point, so they are tested in separate
+//! binaries
+
+use std::{sync::Mutex, vec::Vec};
+
+use trace::{Level, trace_format, trace_handler};
+
+static TRACES: Mutex<Vec<(Level, Vec<u8>)>> = Mutex::new(Vec::new());
+
+#[cfg(not(feature = "interned"))]
+#[trace_handler]
+fn on_trace(level: Level, msg: &str) {
+    TRACES
+        .lock()
+        .unwrap()
+        .push((level, Vec::from(msg.as_bytes())));
+}
+
+// Interned mode passes on the encoded frame rather than the message
+#[cfg(feature = "interned")]
+#[trace_handler]
+fn on_trace(level: Level, frame: &[u8]) {
+    TRACES.lock().unwrap().push((level, Vec::from(frame)));
+}
+
+#[test]
+fn handler_function_gets_level_and_message() {
+    trace_format(Level::Warning, format_args!("{} {}", "motor", 3));
+    #[cfg(feature = "deferred")]
+    trace::drain_to_handlers();
+
+    let traces = TRACES.lock().unwrap();
+    assert_eq!(1, traces.len());
+    assert_eq!(Level::Warning, traces[0].0);
+    if cfg!(not(feature = "interned")) {
+        assert_eq!(b"motor 3", traces[0].1.as_slice());
+    }
+}
@@ -0,0 +1,30 @@
+//! The `(level, msg, callsite)` form of a `#[trace_handler]` function, which is called
+//! through the `_on_trace_record` entry point
+#![cfg(not(feature = "interned"))]
+
+use std::{string::String, sync::Mutex, vec::Vec};
+
+use trace::{Callsite, Level, trace_format, trace_handler};
+
+static TRACES: Mutex<Vec<(Level, String, &str, u32)>> = Mutex::new(Vec::new());
+
+#[trace_handler]
+fn on_trace(level: Level, msg: &str, callsite: &Callsite) {
+    TRACES
+        .lock()
+        .unwrap()
+        .push((level, String::from(msg), callsite.file(), callsite.line()));
+}
+
+#[test]
+fn handler_function_gets_callsite() {
+    let line = line!() + 1;
+    trace_format(Level::Error, format_args!("{} {}", "motor", 3));
+    #[cfg(feature = "deferred")]
+    trace::drain_to_handlers();
+
+    assert_eq!(
+        vec![(Level::Error, String::from("motor 3"), file!(), line)],
+        *TRACES.lock().unwrap()
+    );
+}
@@ -0,0 +1,39 @@
+//! The `(record)` form of a `#[trace_handler]` function, which is called through the
+//! `_on_trace_record` entry point
+
+use std::{sync::Mutex, vec::Vec};
+
+use trace::{Level, Record, trace_format, trace_handler};
+
+/// Level, bytes, file and line of a record
+type Trace = (Level, Vec<u8>, &'static str, u32);
+
+static TRACES: Mutex<Vec<Trace>> = Mutex::new(Vec::new());
+
+#[trace_handler]
+fn on_trace(record: &Record) {
+    TRACES.lock().unwrap().push((
+        record.level(),
+        Vec::from(record.bytes()),
+        record.callsite().file(),
+        record.callsite().line(),
+    ));
+}
+
+#[test]
+fn handler_function_gets_record() {
+    let line = line!() + 1;
+    trace_format(Level::Info, format_args!("{} {}", "motor", 3));
+    #[cfg(feature = "deferred")]
+    trace::drain_to_handlers();
+
+    let traces = TRACES.lock().unwrap();
+    assert_eq!(1, traces.len());
+    let (level, bytes, file, traced_line) = &traces[0];
+    assert_eq!(Level::Info, *level);
+    assert_eq!((file!(), line), (*file, *traced_line));
+    // Interned mode passes on the encoded frame rather than the message
+    if cfg!(not(feature = "interned")) {
+        assert_eq!(b"motor 3", bytes.as_slice());
+    }
+}
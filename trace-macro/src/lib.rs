@@ -1,26 +1,181 @@
 use proc_macro::TokenStream;
 use proc_macro_crate::{FoundCrate, crate_name};
 use quote::quote;
-use syn::{ItemFn, parse_macro_input};
+use syn::{
+    Expr, Ident, Item, ItemFn, ItemStatic, LitStr, Token,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+};
 
 const CRATE_NAME: &str = "trace";
 
+/// Path of the trace crate as seen from the crate using the macro
+fn crate_path() -> proc_macro2::TokenStream {
+    match crate_name(CRATE_NAME) {
+        // Examples of the trace crate use it as a dependency
+        Ok(FoundCrate::Itself) if std::env::var_os("CARGO_BIN_NAME").is_some() => {
+            let ident = syn::Ident::new(CRATE_NAME, proc_macro2::Span::call_site());
+            quote!(::#ident)
+        }
+        Ok(FoundCrate::Itself) => quote!(crate),
+        Ok(FoundCrate::Name(name)) => {
+            let ident = syn::Ident::new(&name, proc_macro2::Span::call_site());
+            quote!(#ident)
+        }
+        Err(_) => panic!("Could not find the `{}` crate.", CRATE_NAME),
+    }
+}
+
 /// Helper macro to allow a user to define an extern trace_write function
-/// with a closure
+/// with a closure, or to register a static implementing `TraceHandler`
 #[proc_macro_attribute]
 pub fn trace_handler(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let crate_path = crate_path();
+
+    match parse_macro_input!(item as Item) {
+        Item::Fn(input) => handler_function(input, crate_path),
+        Item::Static(input) => handler_static(input, crate_path),
+        item => syn::Error::new_spanned(
+            item,
+            "#[trace_handler] can only be applied to functions and statics",
+        )
+        .to_compile_error()
+        .into(),
+    }
+}
+
+/// Register a function called by the panic handler of the `panic-handler` feature, after the panic
+/// has been traced and the trace handler flushed. It takes either no arguments or
+/// `(info: &PanicInfo)`, and never returns
+#[proc_macro_attribute]
+pub fn trace_panic_hook(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as ItemFn);
     let name = &input.sig.ident;
 
-    let crate_path = match crate_name(CRATE_NAME) {
-        Ok(FoundCrate::Itself) => quote!(crate),
-        Ok(FoundCrate::Name(name)) => {
-            let ident = syn::Ident::new(&name, proc_macro2::Span::call_site());
-            quote!(#ident)
+    let call = match input.sig.inputs.len() {
+        0 => quote!(#name()),
+        1 => quote!(#name(info)),
+        _ => {
+            return syn::Error::new_spanned(
+                &input.sig,
+                "#[trace_panic_hook] functions must have the arguments () or (info: &PanicInfo)",
+            )
+            .to_compile_error()
+            .into();
         }
-        Err(_) => panic!("Could not find the `{}` crate.", CRATE_NAME),
     };
 
+    let expanded = quote! {
+        #input
+
+        // Export an extern entry point for the panic hook
+        #[unsafe(no_mangle)]
+        #[allow(improper_ctypes_definitions, unused_variables)]
+        pub extern "C" fn _on_trace_panic(info: &core::panic::PanicInfo) -> ! {
+            #call
+        }
+    };
+
+    expanded.into()
+}
+
+/// Register the timestamp source of trace records. The function takes no arguments and returns the
+/// current time in ticks as a `u64`
+#[proc_macro_attribute]
+pub fn trace_timestamp(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemFn);
+    let name = &input.sig.ident;
+
+    if !input.sig.inputs.is_empty() {
+        return syn::Error::new_spanned(
+            &input.sig,
+            "#[trace_timestamp] functions must take no arguments and return the time as a u64",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let expanded = quote! {
+        #input
+
+        // Export an extern entry point for the timestamp source
+        #[unsafe(no_mangle)]
+        pub extern "C" fn _on_trace_timestamp() -> u64 {
+            #name()
+        }
+    };
+
+    expanded.into()
+}
+
+/// Register the source of the ID of the core a trace is made on, for targets with several cores.
+/// The function takes no arguments and returns the ID as a `u32`, starting from 0
+#[proc_macro_attribute]
+pub fn trace_core_id(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemFn);
+    let name = &input.sig.ident;
+
+    if !input.sig.inputs.is_empty() {
+        return syn::Error::new_spanned(
+            &input.sig,
+            "#[trace_core_id] functions must take no arguments and return the core ID as a u32",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let expanded = quote! {
+        #input
+
+        // Export an extern entry point for the core ID source
+        #[unsafe(no_mangle)]
+        pub extern "C" fn _on_trace_core_id() -> u32 {
+            #name()
+        }
+    };
+
+    expanded.into()
+}
+
+/// Export the entry points of a static implementing `TraceHandler`
+fn handler_static(input: ItemStatic, crate_path: proc_macro2::TokenStream) -> TokenStream {
+    let name = &input.ident;
+
+    let expanded = quote! {
+        #input
+
+        // Export extern entry points for the trace handler
+        #[unsafe(no_mangle)]
+        #[allow(improper_ctypes_definitions)]
+        pub unsafe extern "C" fn _on_trace_record(
+            _level: #crate_path::Level,
+            _msg: *const u8,
+            _msg_len: usize,
+            record: *const #crate_path::Record,
+        ) {
+            let record = unsafe { &*record };
+            #crate_path::TraceHandler::log(&#name, record);
+        }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn _on_trace_enabled(level: #crate_path::Level) -> bool {
+            #crate_path::TraceHandler::enabled(&#name, level)
+        }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn _on_trace_flush() {
+            #crate_path::TraceHandler::flush(&#name);
+        }
+    };
+
+    expanded.into()
+}
+
+/// Export the entry point of a trace handler function
+fn handler_function(input: ItemFn, crate_path: proc_macro2::TokenStream) -> TokenStream {
+    let name = &input.sig.ident;
+
     // Validate the signature
     let args: Vec<_> = input
         .sig
@@ -32,26 +187,262 @@ pub fn trace_handler(_attr: TokenStream, item: TokenStream) -> TokenStream {
         })
         .collect();
 
-    if args.len() != 2 {
+    if !(1..=3).contains(&args.len()) {
         return syn::Error::new_spanned(
             &input.sig,
-            "#[trace_handler] functions must have exactly two arguments: (level: Level, msg: &str)",
+            "#[trace_handler] functions must have the arguments (record: &Record), (level: Level, msg: &str) or (level: Level, msg: &str, callsite: &Callsite). Use `frame: &[u8]` instead of `msg: &str` for raw messages",
         )
         .to_compile_error()
         .into();
     }
 
-    let expanded = quote! {
-        #input
+    let call = match args.len() {
+        1 => quote!(#name(record)),
+        _ => {
+            // A byte slice message means the handler wants the raw frames of the `interned` feature
+            let msg = if is_byte_slice(&args[1].ty) {
+                quote!(slice)
+            } else {
+                quote!(#crate_path::__trace_handler_str!(slice))
+            };
 
-        // Export an extern entry point for the trace function
-        #[unsafe(no_mangle)]
-        pub unsafe extern "C" fn _on_trace(level: #crate_path::Level, msg: *const u8, msg_len: usize) {
-            let slice = unsafe { core::slice::from_raw_parts(msg, msg_len) };
-            let string = str::from_utf8(slice).expect("Invalid UTF-8");
-            #name(level, string);
+            if args.len() == 3 {
+                quote!(#name(level, #msg, record.callsite()))
+            } else {
+                quote!(#name(level, #msg))
+            }
+        }
+    };
+
+    // Handlers taking a record or a callsite export `_on_trace_record`. Otherwise, `_on_trace` is
+    // exported and the trace crate forwards to it
+    let expanded = if args.len() == 2 {
+        quote! {
+            #input
+
+            // Export an extern entry point for the trace function
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn _on_trace(level: #crate_path::Level, msg: *const u8, msg_len: usize) {
+                let slice = unsafe { core::slice::from_raw_parts(msg, msg_len) };
+                #call;
+            }
+        }
+    } else {
+        quote! {
+            #input
+
+            // Export an extern entry point for the trace function
+            #[unsafe(no_mangle)]
+            #[allow(improper_ctypes_definitions, unused_variables)]
+            pub unsafe extern "C" fn _on_trace_record(
+                level: #crate_path::Level,
+                msg: *const u8,
+                msg_len: usize,
+                record: *const #crate_path::Record,
+            ) {
+                let slice = unsafe { core::slice::from_raw_parts(msg, msg_len) };
+                let record = unsafe { &*record };
+                #call;
+            }
         }
     };
 
     expanded.into()
 }
+
+fn is_byte_slice(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Reference(reference) => matches!(&*reference.elem, syn::Type::Slice(_)),
+        _ => false,
+    }
+}
+
+/// Arguments of `__trace_encode!`: the frame, the format string and the format arguments
+struct EncodeInput {
+    frame: Ident,
+    format: LitStr,
+    args: Punctuated<Expr, Token![,]>,
+}
+
+impl Parse for EncodeInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let frame = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let format = input.parse()?;
+        let args = match input.is_empty() {
+            true => Punctuated::new(),
+            false => {
+                input.parse::<Token![,]>()?;
+                Punctuated::parse_terminated(input)?
+            }
+        };
+        Ok(Self {
+            frame,
+            format,
+            args,
+        })
+    }
+}
+
+/// Encode the arguments of a trace macro into a frame of the `interned` feature. As the format
+/// string is only rendered on the host, this checks at compile time that every placeholder refers
+/// to an argument with a spec the host decoder can render, and that every argument is used. Arguments shown as bits, such as with `{:x}`,
+/// are encoded as unsigned numbers, so that negative numbers decode in two's complement
+#[doc(hidden)]
+#[proc_macro]
+pub fn __trace_encode(input: TokenStream) -> TokenStream {
+    let EncodeInput {
+        frame,
+        format,
+        args,
+    } = parse_macro_input!(input as EncodeInput);
+
+    let placeholders = match placeholders(&format.value()) {
+        Ok(placeholders) => placeholders,
+        Err(message) => {
+            return syn::Error::new_spanned(format, message)
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let mut errors = Vec::new();
+    for placeholder in &placeholders {
+        if placeholder.argument >= args.len() {
+            errors.push(syn::Error::new_spanned(
+                &format,
+                match args.len() {
+                    1 => format!(
+                        "invalid reference to positional argument {} (there is 1 argument)",
+                        placeholder.argument
+                    ),
+                    count => format!(
+                        "invalid reference to positional argument {} (there are {} arguments)",
+                        placeholder.argument, count
+                    ),
+                },
+            ));
+        }
+    }
+
+    let mut pushes = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let uses: Vec<_> = placeholders
+            .iter()
+            .filter(|placeholder| placeholder.argument == i)
+            .collect();
+        if uses.is_empty() {
+            errors.push(syn::Error::new_spanned(arg, "argument never used"));
+        } else if uses.iter().any(|placeholder| placeholder.bits)
+            && !uses.iter().all(|placeholder| placeholder.bits)
+        {
+            errors.push(syn::Error::new_spanned(
+                arg,
+                "an argument can't be formatted both as bits and as a number in interned mode, \
+                 pass it once for each",
+            ));
+        } else if uses[0].bits {
+            pushes.push(quote!(#frame.push_bits(&(#arg));));
+        } else {
+            pushes.push(quote!(#frame.push(&(#arg));));
+        }
+    }
+
+    if let Some(error) = errors.into_iter().reduce(|mut error, next| {
+        error.combine(next);
+        error
+    }) {
+        return error.to_compile_error().into();
+    }
+
+    quote!(#(#pushes)*).into()
+}
+
+/// A placeholder of a format string, such as `{}` or `{1:x}`
+struct Placeholder {
+    argument: usize,
+    /// Shown as bits with `x`, `X`, `o` or `b`
+    bits: bool,
+}
+
+/// Parse the placeholders of a format string the way `format_args!` numbers them. Only the
+/// placeholders the host decoder can render are accepted
+fn placeholders(format: &str) -> Result<Vec<Placeholder>, String> {
+    let mut res = Vec::new();
+    let mut next = 0;
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => {}
+            '}' if chars.next_if_eq(&'}').is_some() => {}
+            '}' => return Err("unmatched `}` in format string".into()),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err("unmatched `{` in format string".into()),
+                    }
+                }
+
+                let (argument, spec) = placeholder.split_once(':').unwrap_or((&placeholder, ""));
+                let argument = match argument {
+                    "" => {
+                        next += 1;
+                        next - 1
+                    }
+                    _ => argument.parse().map_err(|_| {
+                        format!(
+                            "named argument `{}` is not supported in interned mode, pass it as a \
+                             positional argument",
+                            argument
+                        )
+                    })?,
+                };
+                // `*` can still be a fill character
+                if !supported_spec(spec) && spec.contains(['$', '*']) {
+                    return Err(
+                        "width and precision arguments are not supported in interned mode".into(),
+                    );
+                }
+                if !supported_spec(spec) {
+                    return Err(format!(
+                        "format spec `{{:{}}}` is not supported in interned mode, the host decoder \
+                         renders `[[fill]align][+][#][0][width][.precision][type]` with the types \
+                         `?`, `x`, `X`, `o`, `b` and `e`",
+                        spec
+                    ));
+                }
+                res.push(Placeholder {
+                    argument,
+                    bits: spec.ends_with(['x', 'X', 'o', 'b']),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(res)
+}
+
+/// Check a format spec against the grammar the host decoder parses in `interned::Spec::parse`
+fn supported_spec(spec: &str) -> bool {
+    let is_align = |c| matches!(c, '<' | '^' | '>');
+    let mut chars = spec.chars().peekable();
+    if spec.chars().nth(1).is_some_and(is_align) {
+        chars.next();
+        chars.next();
+    } else {
+        chars.next_if(|&c| is_align(c));
+    }
+
+    chars.next_if_eq(&'+');
+    chars.next_if_eq(&'#');
+    chars.next_if_eq(&'0');
+    while chars.next_if(char::is_ascii_digit).is_some() {}
+    if chars.next_if_eq(&'.').is_some() {
+        while chars.next_if(char::is_ascii_digit).is_some() {}
+    }
+    chars.next_if(|c| matches!(c, '?' | 'x' | 'X' | 'o' | 'b' | 'e'));
+    chars.next().is_none()
+}